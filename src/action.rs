@@ -0,0 +1,267 @@
+use std::ffi::OsString;
+use std::fs::{self, File, FileTimes, OpenOptions};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::conflict;
+
+// What is done with each file once its destination is known.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Move,
+    Copy,
+    Hardlink,
+    Symlink,
+}
+
+impl Action {
+    pub fn parse(action: &str) -> Option<Action> {
+        match action {
+            "move" => Some(Action::Move),
+            "copy" => Some(Action::Copy),
+            "hardlink" => Some(Action::Hardlink),
+            "symlink" => Some(Action::Symlink),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Move => "move",
+            Action::Copy => "copy",
+            Action::Hardlink => "hardlink",
+            Action::Symlink => "symlink",
+        }
+    }
+
+    // "File {past_tense} to ..."
+    pub fn past_tense(&self) -> &'static str {
+        match self {
+            Action::Move => "moved",
+            Action::Copy => "copied",
+            Action::Hardlink => "hard linked",
+            Action::Symlink => "symlinked",
+        }
+    }
+
+    // Whether the source is left where it was.
+    pub fn keeps_source(&self) -> bool {
+        !matches!(self, Action::Move)
+    }
+}
+
+// Puts `source` at `destination`. Unless `overwrite` is set, an `AlreadyExists` error is
+// returned if something is already there, and the existing file is left untouched.
+pub fn perform(action: Action, source: &Path, destination: &Path, overwrite: bool) -> io::Result<()> {
+    match (action, overwrite) {
+        (Action::Move, _) => {
+            let result = if overwrite {
+                fs::rename(source, destination)
+            } else {
+                conflict::rename_no_clobber(source, destination)
+            };
+            match result {
+                Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                    move_across_devices(source, destination, overwrite)
+                }
+                result => result,
+            }
+        }
+        (_, false) => create(action, source, destination),
+        // Created next to the destination first, so the existing file is only replaced once
+        // the new one is complete
+        (_, true) => {
+            let temporary = temporary_path(destination);
+            let _ = fs::remove_file(&temporary);
+            create(action, source, &temporary)?;
+            fs::rename(&temporary, destination).inspect_err(|_| {
+                let _ = fs::remove_file(&temporary);
+            })
+        }
+    }
+}
+
+// Whether `destination` is what `action` makes of `source`, for telling if a file of an
+// interrupted run was placed before the run could record it.
+pub fn is_done(action: Action, source: &Path, destination: &Path) -> bool {
+    let Ok(placed) = fs::symlink_metadata(destination) else {
+        return false;
+    };
+    match action {
+        Action::Move => fs::symlink_metadata(source).is_err(),
+        Action::Copy => fs::metadata(source).is_ok_and(|original| {
+            original.len() == placed.len()
+                && checksum(source).ok().is_some_and(|crc| checksum(destination).ok() == Some(crc))
+        }),
+        Action::Hardlink => fs::metadata(source).is_ok_and(|original| same_file(&original, &placed)),
+        Action::Symlink => fs::read_link(destination).is_ok_and(|target| target == source),
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(_: &fs::Metadata, _: &fs::Metadata) -> bool {
+    false
+}
+
+fn create(action: Action, source: &Path, destination: &Path) -> io::Result<()> {
+    match action {
+        Action::Move => unreachable!("moves are renames"),
+        Action::Copy => copy_no_clobber(source, destination),
+        Action::Hardlink => fs::hard_link(source, destination),
+        Action::Symlink => symlink(source, destination),
+    }
+}
+
+// A rename cannot cross file systems, so the file is copied next to the destination, checked
+// against the original and only then put in place and the original deleted.
+fn move_across_devices(source: &Path, destination: &Path, overwrite: bool) -> io::Result<()> {
+    let temporary = temporary_path(destination);
+    let _ = fs::remove_file(&temporary);
+
+    let result = copy_verified(source, &temporary).and_then(|_| {
+        if overwrite {
+            fs::rename(&temporary, destination)
+        } else {
+            conflict::rename_no_clobber(&temporary, destination)
+        }
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temporary);
+        return Err(e);
+    }
+    fs::remove_file(source)
+}
+
+fn copy_verified(source: &Path, destination: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(source)?.file_type().is_symlink() {
+        return symlink(&fs::read_link(source)?, destination);
+    }
+
+    copy_no_clobber(source, destination)?;
+    let (original, copy) = (fs::metadata(source)?, fs::metadata(destination)?);
+    if original.len() != copy.len() || checksum(source)? != checksum(destination)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("The copy of {:?} does not match the original", source),
+        ));
+    }
+    Ok(())
+}
+
+// CRC-32 (IEEE) of the file's contents.
+fn checksum(path: &Path) -> io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut crc = !0u32;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(!crc);
+        }
+        for byte in &buffer[..read] {
+            crc ^= u32::from(*byte);
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+            }
+        }
+    }
+}
+
+// Copies the contents, permissions and access and modification times of `source`, and waits
+// for them to reach the disk.
+fn copy_no_clobber(source: &Path, destination: &Path) -> io::Result<()> {
+    let mut reader = File::open(source)?;
+    let metadata = reader.metadata()?;
+    let mut writer = OpenOptions::new().write(true).create_new(true).open(destination)?;
+
+    let result = io::copy(&mut reader, &mut writer).and_then(|_| {
+        let mut times = FileTimes::new().set_modified(metadata.modified()?);
+        if let Ok(accessed) = metadata.accessed() {
+            times = times.set_accessed(accessed);
+        }
+        writer.set_times(times)?;
+        writer.set_permissions(metadata.permissions())?;
+        writer.sync_all()
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(destination);
+    }
+    result
+}
+
+#[cfg(unix)]
+fn symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, destination)
+}
+
+#[cfg(windows)]
+fn symlink(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(source, destination)
+}
+
+// `2024/5/photo.jpg` becomes `2024/5/.photo.jpg.dorg-tmp`.
+fn temporary_path(destination: &Path) -> PathBuf {
+    let mut name = OsString::from(".");
+    name.push(destination.file_name().unwrap_or_default());
+    name.push(".dorg-tmp");
+    destination.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_actions_keep_source_and_existing_files() {
+        let temp_dir = TempDir::new("test_action").expect("Failed to create temp dir");
+        let source = temp_dir.path().join("source.txt");
+        fs::write(&source, "new").unwrap();
+        let modified = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        File::options().write(true).open(&source).unwrap().set_modified(modified).unwrap();
+
+        for action in [Action::Copy, Action::Hardlink, Action::Symlink] {
+            let destination = temp_dir.path().join(action.name());
+            perform(action, &source, &destination, false).expect("Failed to perform action");
+            assert_eq!(fs::read_to_string(&destination).unwrap(), "new");
+            assert_eq!(fs::metadata(&destination).unwrap().modified().unwrap(), modified);
+
+            let error = perform(action, &source, &destination, false).expect_err("Action should not clobber");
+            assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        }
+        assert!(fs::symlink_metadata(temp_dir.path().join("symlink")).unwrap().file_type().is_symlink());
+
+        let existing = temp_dir.path().join("existing.txt");
+        fs::write(&existing, "old").unwrap();
+        perform(Action::Copy, &source, &existing, true).expect("Failed to overwrite");
+        assert_eq!(fs::read_to_string(&existing).unwrap(), "new");
+        assert!(!temporary_path(&existing).exists());
+        assert!(source.exists());
+    }
+
+    #[test]
+    fn test_move_across_devices() {
+        let temp_dir = TempDir::new("test_action").expect("Failed to create temp dir");
+        let source = temp_dir.path().join("source.txt");
+        let destination = temp_dir.path().join("destination.txt");
+        fs::write(&source, "123456789").unwrap();
+        assert_eq!(checksum(&source).unwrap(), 0xCBF4_3926);
+
+        fs::write(&destination, "old").unwrap();
+        let error = move_across_devices(&source, &destination, false).expect_err("Move should not clobber");
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert!(source.exists());
+        assert!(!temporary_path(&destination).exists());
+
+        move_across_devices(&source, &destination, true).expect("Failed to move");
+        assert!(!source.exists());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "123456789");
+        assert!(!temporary_path(&destination).exists());
+    }
+}
@@ -0,0 +1,132 @@
+// Minimal reader for ISO base media file format boxes, the container behind HEIC, CR3 and
+// MP4/QuickTime files. Only walks box headers; callers read the payloads they care about.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+#[derive(Clone, Copy, Debug)]
+pub struct BoxHeader {
+    pub kind: [u8; 4],
+    // Offset of the first payload byte, after the size, type and any 64-bit size.
+    pub start: u64,
+    // Offset just past the last payload byte.
+    pub end: u64,
+}
+
+// Reads the header of the box at `offset`, or returns `None` when there is no complete box
+// before `limit`.
+pub fn read_header<R: Read + Seek>(reader: &mut R, offset: u64, limit: u64) -> io::Result<Option<BoxHeader>> {
+    if offset + 8 > limit {
+        return Ok(None);
+    }
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+
+    let size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+    let kind = [header[4], header[5], header[6], header[7]];
+    let (start, end) = match size {
+        0 => (offset + 8, limit),
+        1 => {
+            let mut large_size = [0; 8];
+            reader.read_exact(&mut large_size)?;
+            (offset + 16, offset.saturating_add(u64::from_be_bytes(large_size)))
+        }
+        size => (offset + 8, offset + size),
+    };
+
+    if end < start || end > limit {
+        return Ok(None);
+    }
+    Ok(Some(BoxHeader { kind, start, end }))
+}
+
+// Finds the first child box of type `kind` between `start` and `end`.
+pub fn find<R: Read + Seek>(reader: &mut R, start: u64, end: u64, kind: &[u8; 4]) -> io::Result<Option<BoxHeader>> {
+    let mut offset = start;
+    while let Some(header) = read_header(reader, offset, end)? {
+        if &header.kind == kind {
+            return Ok(Some(header));
+        }
+        offset = header.end;
+    }
+    Ok(None)
+}
+
+// Follows a path of nested box types, e.g. `[b"mdia", b"mdhd"]`.
+pub fn find_path<R: Read + Seek>(reader: &mut R, start: u64, end: u64, path: &[&[u8; 4]]) -> io::Result<Option<BoxHeader>> {
+    let mut found = None;
+    let (mut start, mut end) = (start, end);
+    for kind in path {
+        match find(reader, start, end, kind)? {
+            Some(header) => {
+                (start, end) = (header.start, header.end);
+                found = Some(header);
+            }
+            None => return Ok(None),
+        }
+    }
+    Ok(found)
+}
+
+pub fn is_bmff(header: &[u8]) -> bool {
+    header.len() >= 8 && &header[4..8] == b"ftyp"
+}
+
+pub fn read_u8<R: Read>(reader: &mut R) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    reader.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+pub fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+pub fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+// Reads a big-endian unsigned integer of 0, 4 or 8 bytes, as used by the `iloc` box.
+pub fn read_sized<R: Read>(reader: &mut R, size: u8) -> io::Result<u64> {
+    match size {
+        0 => Ok(0),
+        4 => read_u32(reader).map(u64::from),
+        8 => read_u64(reader),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "Unsupported field size")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_find_nested_box() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&[0, 0, 0, 12, b'f', b't', b'y', b'p', b'h', b'e', b'i', b'c']);
+        data.extend_from_slice(&[0, 0, 0, 20, b'm', b'o', b'o', b'v']);
+        data.extend_from_slice(&[0, 0, 0, 12, b'm', b'v', b'h', b'd', 1, 2, 3, 4]);
+
+        let mut reader = Cursor::new(&data);
+        let moov = find(&mut reader, 0, data.len() as u64, b"moov").unwrap().expect("moov not found");
+        let header = find(&mut reader, moov.start, moov.end, b"mvhd").unwrap().expect("mvhd not found");
+
+        assert_eq!(&header.kind, b"mvhd");
+        assert_eq!((header.start, header.end), (28, 32));
+        assert!(find(&mut reader, moov.start, moov.end, b"mdhd").unwrap().is_none());
+
+        let nested = find_path(&mut reader, 0, data.len() as u64, &[b"moov", b"mvhd"]).unwrap();
+        assert_eq!(nested.map(|header| header.start), Some(28));
+    }
+}
@@ -0,0 +1,128 @@
+// Sorts files into type categories such as `Images` or `Documents` by their extension.
+
+use std::path::Path;
+
+use crate::sniff::Format;
+
+// Files whose extension is in none of the groups.
+pub const OTHER: &str = "Other";
+
+const BUILT_IN_GROUPS: [(&str, &[&str]); 6] = [
+    (
+        "Images",
+        &[
+            "jpg", "jpeg", "png", "gif", "webp", "bmp", "tif", "tiff", "heic", "heif", "avif", "svg", "ico", "cr2",
+            "cr3", "nef", "arw", "dng", "orf", "rw2", "pef", "raf",
+        ],
+    ),
+    (
+        "Documents",
+        &[
+            "pdf", "doc", "docx", "odt", "rtf", "txt", "md", "xls", "xlsx", "ods", "csv", "ppt", "pptx", "odp", "epub",
+        ],
+    ),
+    ("Archives", &["zip", "rar", "7z", "tar", "gz", "tgz", "bz2", "xz", "zst", "iso", "dmg"]),
+    ("Video", &["mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv", "flv", "3gp", "mpg", "mpeg"]),
+    ("Audio", &["mp3", "m4a", "aac", "flac", "wav", "ogg", "opus", "wma", "aiff"]),
+    (
+        "Code",
+        &[
+            "rs", "py", "js", "ts", "jsx", "tsx", "c", "h", "cpp", "hpp", "cs", "java", "kt", "go", "rb", "php", "sh",
+            "html", "css", "json", "toml", "yaml", "yml", "xml", "sql",
+        ],
+    ),
+];
+
+pub struct Categories {
+    // Checked in order, so user groups come before the built-in ones.
+    groups: Vec<(String, Vec<String>)>,
+}
+
+impl Categories {
+    pub fn new() -> Categories {
+        let groups = BUILT_IN_GROUPS
+            .iter()
+            .map(|(name, extensions)| (name.to_string(), extensions.iter().map(|ext| ext.to_string()).collect()))
+            .collect();
+        Categories { groups }
+    }
+
+    // Replaces the extensions of a category, or adds a new one. An empty list removes the category.
+    pub fn set(&mut self, name: &str, extensions: Vec<String>) {
+        let extensions: Vec<String> = extensions.iter().map(|ext| ext.to_lowercase()).collect();
+        self.groups.retain(|(group, _)| group != name);
+        if !extensions.is_empty() {
+            self.groups.insert(0, (name.to_string(), extensions));
+        }
+    }
+
+    pub fn of(&self, path: &Path) -> &str {
+        match path.extension() {
+            Some(extension) => self.of_extension(&extension.to_string_lossy()),
+            None => OTHER,
+        }
+    }
+
+    // Like `of`, for a file whose contents were recognised as `format`. The file's own extension
+    // is used when it is one the format is saved with, so `.nef` files stay apart from `.tif` ones.
+    pub fn of_format(&self, path: &Path, format: Format) -> &str {
+        let extension = path.extension().map(|extension| extension.to_string_lossy());
+        match extension {
+            Some(extension) if format.accepts(&extension) => self.of_extension(&extension),
+            _ => format.extension().map_or(OTHER, |extension| self.of_extension(extension)),
+        }
+    }
+
+    fn of_extension(&self, extension: &str) -> &str {
+        let extension = extension.to_lowercase();
+        self.groups
+            .iter()
+            .find(|(_, extensions)| extensions.contains(&extension))
+            .map_or(OTHER, |(name, _)| name.as_str())
+    }
+}
+
+impl Default for Categories {
+    fn default() -> Categories {
+        Categories::new()
+    }
+}
+
+// Parses a `NAME=ext,ext,...` category override.
+pub fn parse_group(group: &str) -> Result<(String, Vec<String>), String> {
+    let (name, extensions) = group.split_once('=').ok_or(format!("Invalid type group `{group}`, expected NAME=ext,..."))?;
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(format!("Invalid type group name `{name}`"));
+    }
+    let extensions = extensions
+        .split(',')
+        .map(|ext| ext.trim_start_matches('.'))
+        .filter(|ext| !ext.is_empty())
+        .map(String::from)
+        .collect();
+    Ok((name.to_string(), extensions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_built_in_and_overridden_categories() {
+        let mut categories = Categories::new();
+        assert_eq!(categories.of(Path::new("a/IMG_1.JPG")), "Images");
+        assert_eq!(categories.of(Path::new("report.pdf")), "Documents");
+        assert_eq!(categories.of(Path::new("Makefile")), OTHER);
+        assert_eq!(categories.of(Path::new("model.blend")), OTHER);
+
+        let (name, extensions) = parse_group("Raw=.cr2,NEF").unwrap();
+        categories.set(&name, extensions);
+        categories.set("Code", Vec::new());
+        assert_eq!(categories.of(Path::new("IMG_1.nef")), "Raw");
+        assert_eq!(categories.of(Path::new("IMG_1.jpg")), "Images");
+        assert_eq!(categories.of(Path::new("main.rs")), OTHER);
+
+        assert!(parse_group("Images").is_err());
+        assert!(parse_group("../x=jpg").is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    Skip,
+    Rename,
+    Overwrite,
+    Fail,
+    KeepNewer,
+}
+
+impl ConflictPolicy {
+    pub fn parse(policy: &str) -> Option<ConflictPolicy> {
+        match policy {
+            "skip" => Some(ConflictPolicy::Skip),
+            "rename" => Some(ConflictPolicy::Rename),
+            "overwrite" => Some(ConflictPolicy::Overwrite),
+            "fail" => Some(ConflictPolicy::Fail),
+            "keep-newer" => Some(ConflictPolicy::KeepNewer),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Skip => "skip",
+            ConflictPolicy::Rename => "rename",
+            ConflictPolicy::Overwrite => "overwrite",
+            ConflictPolicy::Fail => "fail",
+            ConflictPolicy::KeepNewer => "keep-newer",
+        }
+    }
+}
+
+// What is already at a destination.
+pub enum Occupant {
+    // A file on disk.
+    Existing,
+    // The source of an earlier operation of the same plan, which is only put there later.
+    Planned(PathBuf),
+}
+
+pub enum Resolution {
+    Move { destination: PathBuf, overwrite: bool },
+    Skip(String),
+    Fail(String),
+}
+
+// Decides what to do with `source` given a `destination` that may already be taken,
+// either on disk or by an earlier operation of the same plan (as reported by `occupant`).
+pub fn resolve(
+    policy: ConflictPolicy,
+    source: &Path,
+    destination: PathBuf,
+    occupant: impl Fn(&Path) -> Option<Occupant>,
+) -> Resolution {
+    let is_taken = |path: &Path| occupant(path).is_some();
+    let Some(current) = occupant(&destination) else {
+        return Resolution::Move { destination, overwrite: false };
+    };
+
+    match policy {
+        ConflictPolicy::Overwrite => Resolution::Move { destination, overwrite: true },
+        ConflictPolicy::Skip => Resolution::Skip(format!("{:?} already exists", destination)),
+        ConflictPolicy::Fail => Resolution::Fail(format!("{:?} already exists", destination)),
+        ConflictPolicy::Rename => {
+            let destination = (2..)
+                .map(|counter| numbered(&destination, counter))
+                .find(|candidate| !is_taken(candidate))
+                .expect("ran out of file name counters");
+            Resolution::Move { destination, overwrite: false }
+        }
+        ConflictPolicy::KeepNewer => {
+            // A planned destination is not on disk yet, the file that is going there is
+            let (current, reason) = match &current {
+                Occupant::Existing => (destination.as_path(), format!("{:?} is not older", destination)),
+                Occupant::Planned(other) => (other.as_path(), format!("{:?} goes to {:?} and is not older", other, destination)),
+            };
+            if is_newer(source, current) {
+                Resolution::Move { destination, overwrite: true }
+            } else {
+                Resolution::Skip(reason)
+            }
+        }
+    }
+}
+
+// `photo.jpg` becomes `photo (2).jpg`, `notes` becomes `notes (2)`.
+fn numbered(path: &Path, counter: u32) -> PathBuf {
+    let mut name = OsString::from(path.file_stem().unwrap_or_default());
+    name.push(format!(" ({counter})"));
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+// When either modification time cannot be read, the file already there is kept.
+fn is_newer(source: &Path, current: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(source), modified(current)) {
+        (Ok(source), Ok(current)) => source > current,
+        _ => false,
+    }
+}
+
+// Renames `source` to `destination` unless something already exists there, in which case
+// an `AlreadyExists` error is returned. On Linux the check and the rename are one atomic step.
+pub fn rename_no_clobber(source: &Path, destination: &Path) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        match renameat2_no_replace(source, destination) {
+            Err(e) if matches!(e.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOSYS)) => {}
+            result => return result,
+        }
+    }
+
+    if destination.symlink_metadata().is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{:?} already exists", destination),
+        ));
+    }
+    fs::rename(source, destination)
+}
+
+#[cfg(target_os = "linux")]
+fn renameat2_no_replace(source: &Path, destination: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let to_c = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    };
+    let (source, destination) = (to_c(source)?, to_c(destination)?);
+
+    let result = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            source.as_ptr(),
+            libc::AT_FDCWD,
+            destination.as_ptr(),
+            libc::RENAME_NOREPLACE,
+        )
+    };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_rename_policy_appends_counter() {
+        let taken = [PathBuf::from("2024/5/IMG_0001.jpg"), PathBuf::from("2024/5/IMG_0001 (2).jpg")];
+        let resolution = resolve(
+            ConflictPolicy::Rename,
+            Path::new("a/IMG_0001.jpg"),
+            PathBuf::from("2024/5/IMG_0001.jpg"),
+            |path| taken.iter().any(|taken| taken == path).then_some(Occupant::Existing),
+        );
+
+        match resolution {
+            Resolution::Move { destination, overwrite } => {
+                assert_eq!(destination, PathBuf::from("2024/5/IMG_0001 (3).jpg"));
+                assert!(!overwrite);
+            }
+            _ => panic!("Expected the file to be moved under a new name"),
+        }
+    }
+
+    #[test]
+    fn test_rename_no_clobber_keeps_existing_file() {
+        let temp_dir = TempDir::new("test_conflict").expect("Failed to create temp dir");
+        let source = temp_dir.path().join("source.txt");
+        let destination = temp_dir.path().join("destination.txt");
+        fs::write(&source, "new").unwrap();
+        File::create(&destination).unwrap();
+
+        let error = rename_no_clobber(&source, &destination).expect_err("Rename should not clobber");
+
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert!(source.exists());
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "");
+    }
+}
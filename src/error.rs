@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::SortType;
+
+#[derive(Debug)]
+pub enum MetadataError {
+    CreationTimeUnavailable,
+    DateUnavailable(SortType),
+    IoError(io::Error)
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataError::CreationTimeUnavailable => write!(f, "Creation time is unavailable"),
+            MetadataError::DateUnavailable(source) => write!(f, "No {} date found", source.name()),
+            MetadataError::IoError(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl Error for MetadataError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MetadataError::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MetadataError {
+    fn from(error: io::Error) -> Self {
+        MetadataError::IoError(error)
+    }
+}
+
+// Whether reading a file's creation or modification time failed because the platform or file
+// system does not keep it. Linux reports a missing birth time as `Unsupported`, other
+// platforms as `Other`.
+pub fn is_time_unavailable(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::Unsupported | io::ErrorKind::Other)
+}
+
+// The file system call that failed, named after the system call it mostly is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoOperation {
+    ReadDir,
+    Stat,
+    Read,
+    Write,
+    Mkdir,
+    Rename,
+    Copy,
+    Link,
+    Remove,
+    Watch,
+}
+
+impl IoOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IoOperation::ReadDir => "read_dir",
+            IoOperation::Stat => "stat",
+            IoOperation::Read => "read",
+            IoOperation::Write => "write",
+            IoOperation::Mkdir => "mkdir",
+            IoOperation::Rename => "rename",
+            IoOperation::Copy => "copy",
+            IoOperation::Link => "link",
+            IoOperation::Remove => "remove",
+            IoOperation::Watch => "watch",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DorgError {
+    // Invalid command line arguments or configuration file.
+    Usage(String),
+    Io { operation: IoOperation, path: PathBuf, source: io::Error },
+    // None of a file's date sources gave a date.
+    Metadata { path: PathBuf, source: MetadataError },
+    // Something is already at a file's destination and `--on-conflict=fail` was given.
+    Conflict { path: PathBuf, reason: String },
+    // No destination can be made for a file, e.g. a template that renders to nothing.
+    Destination { path: PathBuf, reason: String },
+    // There is no journal to undo, or it cannot be used.
+    Journal(String),
+    // With `--keep-going`, this many files could not be organized while the others were.
+    Incomplete { failed: usize },
+    // Ctrl-C was pressed. The files placed until then are in the journal.
+    Interrupted,
+}
+
+impl fmt::Display for DorgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DorgError::Usage(message) | DorgError::Journal(message) => write!(f, "{message}"),
+            DorgError::Io { operation, path, source } => write!(f, "{} {:?} failed: {source}", operation.name(), path),
+            DorgError::Metadata { path, source } => write!(f, "{:?}: {source}", path),
+            DorgError::Conflict { path, reason } | DorgError::Destination { path, reason } => {
+                write!(f, "{:?}: {reason}", path)
+            }
+            DorgError::Incomplete { failed } => write!(f, "{failed} file(s) could not be organized"),
+            DorgError::Interrupted => write!(f, "Interrupted, `dorg resume` finishes the run"),
+        }
+    }
+}
+
+impl Error for DorgError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DorgError::Io { source, .. } => Some(source),
+            DorgError::Metadata { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl DorgError {
+    // The file or directory the error is about, if it is about one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            DorgError::Io { path, .. }
+            | DorgError::Metadata { path, .. }
+            | DorgError::Conflict { path, .. }
+            | DorgError::Destination { path, .. } => Some(path),
+            DorgError::Usage(_) | DorgError::Journal(_) | DorgError::Incomplete { .. } | DorgError::Interrupted => None,
+        }
+    }
+
+    pub fn kind(&self) -> FailureKind {
+        let io_kind = |error: &io::Error| match error.kind() {
+            io::ErrorKind::PermissionDenied => FailureKind::PermissionDenied,
+            io::ErrorKind::CrossesDevices => FailureKind::CrossDevice,
+            // Something appeared at the destination while the file was being placed
+            io::ErrorKind::AlreadyExists => FailureKind::Conflict,
+            _ => FailureKind::Other,
+        };
+        match self {
+            DorgError::Metadata { source: MetadataError::IoError(source), .. } if is_time_unavailable(source) => {
+                FailureKind::NoTimestamp
+            }
+            DorgError::Io { source, .. } | DorgError::Metadata { source: MetadataError::IoError(source), .. } => {
+                io_kind(source)
+            }
+            DorgError::Metadata { .. } => FailureKind::NoTimestamp,
+            DorgError::Conflict { .. } => FailureKind::Conflict,
+            _ => FailureKind::Other,
+        }
+    }
+}
+
+// What kind of trouble a file ran into, for grouping the failures at the end of a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureKind {
+    PermissionDenied,
+    NoTimestamp,
+    Conflict,
+    CrossDevice,
+    Other,
+}
+
+impl FailureKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FailureKind::PermissionDenied => "permission_denied",
+            FailureKind::NoTimestamp => "no_timestamp",
+            FailureKind::Conflict => "conflict",
+            FailureKind::CrossDevice => "cross_device",
+            FailureKind::Other => "other",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            FailureKind::PermissionDenied => "Permission denied",
+            FailureKind::NoTimestamp => "No timestamp",
+            FailureKind::Conflict => "Conflict",
+            FailureKind::CrossDevice => "Cross-device",
+            FailureKind::Other => "Other errors",
+        }
+    }
+}
+
+// Attaches the operation and path to an I/O error, e.g. `fs::metadata(&path).context(Stat, &path)`.
+pub trait IoContext<T> {
+    fn context(self, operation: IoOperation, path: &Path) -> Result<T, DorgError>;
+}
+
+impl<T> IoContext<T> for io::Result<T> {
+    fn context(self, operation: IoOperation, path: &Path) -> Result<T, DorgError> {
+        self.map_err(|source| DorgError::Io { operation, path: path.to_path_buf(), source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_timestamps_are_grouped_together() {
+        let metadata = |error: io::Error| DorgError::Metadata { path: PathBuf::from("/a.jpg"), source: error.into() };
+        for kind in [io::ErrorKind::Unsupported, io::ErrorKind::Other] {
+            let error = io::Error::new(kind, "creation time is not available for the filesystem");
+            assert!(is_time_unavailable(&error));
+            assert_eq!(metadata(error).kind(), FailureKind::NoTimestamp);
+        }
+        assert_eq!(metadata(io::Error::from(io::ErrorKind::PermissionDenied)).kind(), FailureKind::PermissionDenied);
+        let read = DorgError::Io {
+            operation: IoOperation::Read,
+            path: PathBuf::from("/a.jpg"),
+            source: io::Error::from(io::ErrorKind::Unsupported),
+        };
+        assert_eq!(read.kind(), FailureKind::Other);
+    }
+}
@@ -0,0 +1,502 @@
+// Reads the EXIF DateTimeOriginal (and OffsetTimeOriginal) of photos without decoding them.
+// Supports JPEG, TIFF and the TIFF-based RAW formats (CR2, NEF, ARW, DNG, ORF, RW2, PEF),
+// Fujifilm RAF, and HEIC/AVIF and Canon CR3 through their ISO base media boxes.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::{FixedOffset, NaiveDateTime};
+
+use crate::bmff;
+use crate::FileDate;
+
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_DATE_TIME_ORIGINAL: u16 = 0x9003;
+const TAG_OFFSET_TIME_ORIGINAL: u16 = 0x9011;
+const TYPE_ASCII: u16 = 2;
+const MAX_IFD_ENTRIES: u16 = 1024;
+const CANON_UUID: [u8; 16] = [
+    0x85, 0xc0, 0xb6, 0x87, 0x82, 0x0f, 0x11, 0xe0, 0x81, 0x11, 0xf4, 0xce, 0x46, 0x2b, 0x6a, 0x48,
+];
+
+#[derive(Default)]
+struct Tags {
+    date_time_original: Option<String>,
+    offset_time_original: Option<String>,
+}
+
+// Returns `None` for files that are not a supported photo format or carry no usable date.
+pub fn date_taken(path: &Path) -> io::Result<Option<FileDate>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    match read_tags(&mut file, len) {
+        Ok(tags) => Ok(tags.and_then(to_file_date)),
+        // Truncated or malformed metadata is treated like missing metadata
+        Err(e) if matches!(e.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn read_tags<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Option<Tags>> {
+    let mut header = [0; 16];
+    let read = reader.read(&mut header)?;
+    let header = &header[..read];
+
+    if header.starts_with(&[0xFF, 0xD8]) {
+        from_jpeg(reader, 0)
+    } else if is_tiff(header) {
+        from_tiff(reader, 0).map(Some)
+    } else if header.starts_with(b"FUJIFILMCCD-RAW") {
+        reader.seek(SeekFrom::Start(84))?;
+        let jpeg_offset = bmff::read_u32(reader)?;
+        from_jpeg(reader, jpeg_offset.into())
+    } else if bmff::is_bmff(header) {
+        match from_heif(reader, len)? {
+            Some(tags) => Ok(Some(tags)),
+            None => from_cr3(reader, len),
+        }
+    } else {
+        Ok(None)
+    }
+}
+
+// Besides the standard `II*\0` and `MM\0*`, Olympus ORF and Panasonic RW2 use their own magic.
+fn is_tiff(header: &[u8]) -> bool {
+    header.starts_with(b"II*\0")
+        || header.starts_with(b"MM\0*")
+        || header.starts_with(b"IIRO")
+        || header.starts_with(b"IIRS")
+        || header.starts_with(b"IIU\0")
+}
+
+fn from_jpeg<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<Option<Tags>> {
+    let mut offset = start + 2;
+    loop {
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut marker = [0; 2];
+        reader.read_exact(&mut marker)?;
+        if marker[0] != 0xFF {
+            return Ok(None);
+        }
+        match marker[1] {
+            // Fill byte before a marker
+            0xFF => {
+                offset += 1;
+                continue;
+            }
+            // Markers without a length
+            0x01 | 0xD0..=0xD8 => {
+                offset += 2;
+                continue;
+            }
+            // Start of scan or end of image: no more metadata segments
+            0xDA | 0xD9 => return Ok(None),
+            _ => {}
+        }
+
+        let length = u64::from(bmff::read_u16(reader)?);
+        if marker[1] == 0xE1 {
+            let mut signature = [0; 6];
+            reader.read_exact(&mut signature)?;
+            if &signature == b"Exif\0\0" {
+                return from_tiff(reader, offset + 10).map(Some);
+            }
+        }
+        offset += 2 + length;
+    }
+}
+
+fn from_heif<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Option<Tags>> {
+    let Some(meta) = bmff::find(reader, 0, len, b"meta")? else {
+        return Ok(None);
+    };
+    // `meta` is a full box: its children come after the version and flags
+    let children = meta.start + 4;
+
+    let Some(exif_id) = find_exif_item(reader, children, meta.end)? else {
+        return Ok(None);
+    };
+    let Some(item_offset) = find_item_offset(reader, children, meta.end, exif_id)? else {
+        return Ok(None);
+    };
+
+    // The item starts with the offset of the TIFF header, past an `Exif\0\0` prefix
+    reader.seek(SeekFrom::Start(item_offset))?;
+    let tiff_offset = bmff::read_u32(reader)?;
+    from_tiff(reader, add_offset(add_offset(item_offset, 4)?, tiff_offset.into())?).map(Some)
+}
+
+fn find_exif_item<R: Read + Seek>(reader: &mut R, start: u64, end: u64) -> io::Result<Option<u32>> {
+    let Some(iinf) = bmff::find(reader, start, end, b"iinf")? else {
+        return Ok(None);
+    };
+    reader.seek(SeekFrom::Start(iinf.start))?;
+    let version = bmff::read_u8(reader)?;
+    let mut offset = iinf.start + 4 + if version == 0 { 2 } else { 4 };
+
+    while let Some(infe) = bmff::read_header(reader, offset, iinf.end)? {
+        offset = infe.end;
+        if &infe.kind != b"infe" {
+            continue;
+        }
+        reader.seek(SeekFrom::Start(infe.start))?;
+        let version = bmff::read_u8(reader)?;
+        if version < 2 {
+            continue;
+        }
+        reader.seek(SeekFrom::Current(3))?;
+        let item_id = if version == 2 { bmff::read_u16(reader)?.into() } else { bmff::read_u32(reader)? };
+        let _protection_index = bmff::read_u16(reader)?;
+        let mut item_type = [0; 4];
+        reader.read_exact(&mut item_type)?;
+        if &item_type == b"Exif" {
+            return Ok(Some(item_id));
+        }
+    }
+    Ok(None)
+}
+
+// Returns the file offset of the first extent of `item_id` from the `iloc` box.
+fn find_item_offset<R: Read + Seek>(reader: &mut R, start: u64, end: u64, item_id: u32) -> io::Result<Option<u64>> {
+    let Some(iloc) = bmff::find(reader, start, end, b"iloc")? else {
+        return Ok(None);
+    };
+    reader.seek(SeekFrom::Start(iloc.start))?;
+    let version = bmff::read_u8(reader)?;
+    reader.seek(SeekFrom::Current(3))?;
+
+    let sizes = bmff::read_u8(reader)?;
+    let (offset_size, length_size) = (sizes >> 4, sizes & 0x0F);
+    let sizes = bmff::read_u8(reader)?;
+    let base_offset_size = sizes >> 4;
+    let index_size = if version == 1 || version == 2 { sizes & 0x0F } else { 0 };
+    let item_count = if version < 2 { bmff::read_u16(reader)?.into() } else { bmff::read_u32(reader)? };
+
+    for _ in 0..item_count {
+        let id = if version < 2 { bmff::read_u16(reader)?.into() } else { bmff::read_u32(reader)? };
+        let construction_method = if version == 1 || version == 2 { bmff::read_u16(reader)? & 0x0F } else { 0 };
+        let _data_reference_index = bmff::read_u16(reader)?;
+        let base_offset = bmff::read_sized(reader, base_offset_size)?;
+        let extent_count = bmff::read_u16(reader)?;
+
+        let mut first_extent = None;
+        for _ in 0..extent_count {
+            bmff::read_sized(reader, index_size)?;
+            let extent_offset = bmff::read_sized(reader, offset_size)?;
+            bmff::read_sized(reader, length_size)?;
+            first_extent.get_or_insert(extent_offset);
+        }
+
+        // Only items stored directly in the file are supported
+        if id == item_id && construction_method == 0 {
+            return first_extent.map(|extent_offset| add_offset(base_offset, extent_offset)).transpose();
+        }
+    }
+    Ok(None)
+}
+
+// Canon CR3 keeps its EXIF IFD as a small TIFF in the `CMT2` box of a Canon `uuid` box.
+fn from_cr3<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Option<Tags>> {
+    let Some(moov) = bmff::find(reader, 0, len, b"moov")? else {
+        return Ok(None);
+    };
+    let mut offset = moov.start;
+    while let Some(header) = bmff::read_header(reader, offset, moov.end)? {
+        offset = header.end;
+        if &header.kind != b"uuid" {
+            continue;
+        }
+        let mut uuid = [0; 16];
+        reader.read_exact(&mut uuid)?;
+        if uuid != CANON_UUID {
+            continue;
+        }
+        for kind in [b"CMT2", b"CMT1"] {
+            if let Some(cmt) = bmff::find(reader, header.start + 16, header.end, kind)? {
+                let tags = from_tiff(reader, cmt.start)?;
+                if tags.date_time_original.is_some() {
+                    return Ok(Some(tags));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+struct Tiff<'a, R> {
+    reader: &'a mut R,
+    base: u64,
+    little_endian: bool,
+}
+
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: [u8; 4],
+}
+
+fn from_tiff<R: Read + Seek>(reader: &mut R, base: u64) -> io::Result<Tags> {
+    reader.seek(SeekFrom::Start(base))?;
+    let mut header = [0; 8];
+    reader.read_exact(&mut header)?;
+    let little_endian = match &header[..2] {
+        b"II" => true,
+        b"MM" => false,
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid TIFF byte order")),
+    };
+
+    let mut tiff = Tiff { reader, base, little_endian };
+    let ifd0 = tiff.u32(&header[4..8]);
+    let mut tags = Tags::default();
+
+    let entries = tiff.read_ifd(ifd0)?;
+    tiff.collect(&entries, &mut tags)?;
+    if let Some(exif) = entries.iter().find(|entry| entry.tag == TAG_EXIF_IFD) {
+        let exif_offset = tiff.u32(&exif.value);
+        let entries = tiff.read_ifd(exif_offset)?;
+        tiff.collect(&entries, &mut tags)?;
+    }
+    Ok(tags)
+}
+
+impl<R: Read + Seek> Tiff<'_, R> {
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.little_endian { u16::from_le_bytes(bytes) } else { u16::from_be_bytes(bytes) }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.little_endian { u32::from_le_bytes(bytes) } else { u32::from_be_bytes(bytes) }
+    }
+
+    fn read_ifd(&mut self, offset: u32) -> io::Result<Vec<Entry>> {
+        self.reader.seek(SeekFrom::Start(add_offset(self.base, offset.into())?))?;
+        let mut count = [0; 2];
+        self.reader.read_exact(&mut count)?;
+        let count = self.u16(&count).min(MAX_IFD_ENTRIES);
+
+        let mut entries = Vec::with_capacity(count.into());
+        for _ in 0..count {
+            let mut raw = [0; 12];
+            self.reader.read_exact(&mut raw)?;
+            entries.push(Entry {
+                tag: self.u16(&raw[0..2]),
+                kind: self.u16(&raw[2..4]),
+                count: self.u32(&raw[4..8]),
+                value: [raw[8], raw[9], raw[10], raw[11]],
+            });
+        }
+        Ok(entries)
+    }
+
+    fn collect(&mut self, entries: &[Entry], tags: &mut Tags) -> io::Result<()> {
+        for entry in entries {
+            let target = match entry.tag {
+                TAG_DATE_TIME_ORIGINAL => &mut tags.date_time_original,
+                TAG_OFFSET_TIME_ORIGINAL => &mut tags.offset_time_original,
+                _ => continue,
+            };
+            if entry.kind == TYPE_ASCII && target.is_none() {
+                *target = Some(self.read_ascii(entry)?);
+            }
+        }
+        Ok(())
+    }
+
+    fn read_ascii(&mut self, entry: &Entry) -> io::Result<String> {
+        // Values of up to 4 bytes are stored in the entry itself
+        let bytes = if entry.count <= 4 {
+            entry.value[..entry.count as usize].to_vec()
+        } else {
+            let mut bytes = vec![0; entry.count.min(64) as usize];
+            let offset = add_offset(self.base, self.u32(&entry.value).into())?;
+            self.reader.seek(SeekFrom::Start(offset))?;
+            self.reader.read_exact(&mut bytes)?;
+            bytes
+        };
+        let text = String::from_utf8_lossy(&bytes);
+        Ok(text.trim_end_matches(['\0', ' ']).to_string())
+    }
+}
+
+// Offsets read from the file can be made up to point past anything a file can hold.
+fn add_offset(base: u64, offset: u64) -> io::Result<u64> {
+    base.checked_add(offset).ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))
+}
+
+fn to_file_date(tags: Tags) -> Option<FileDate> {
+    // Cameras without a clock set write all zeros or blanks
+    let datetime = NaiveDateTime::parse_from_str(tags.date_time_original.as_deref()?, "%Y:%m:%d %H:%M:%S").ok()?;
+    let offset = tags.offset_time_original.as_deref().and_then(parse_offset);
+
+    Some(match offset {
+        Some(offset) => FileDate::Instant(datetime.and_local_timezone(offset).single()?.to_utc()),
+        None => FileDate::Local(datetime),
+    })
+}
+
+// Parses offsets such as `+09:00` or `-05:30`.
+fn parse_offset(offset: &str) -> Option<FixedOffset> {
+    let sign = match offset.get(..1)? {
+        "+" => 1,
+        "-" => -1,
+        _ => return None,
+    };
+    let (hours, minutes) = offset.get(1..)?.split_once(':')?;
+    let seconds = hours.parse::<i32>().ok()? * 3600 + minutes.parse::<i32>().ok()? * 60;
+    FixedOffset::east_opt(sign * seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use std::fs;
+    use tempdir::TempDir;
+
+    // A little-endian TIFF whose EXIF IFD holds DateTimeOriginal and, optionally, OffsetTimeOriginal.
+    fn tiff(magic: &[u8; 4], date: &str, offset: Option<&str>) -> Vec<u8> {
+        let date = format!("{date}\0");
+        let entry_count: u16 = if offset.is_some() { 2 } else { 1 };
+        let exif_ifd = 8 + 2 + 12 + 4;
+        let values = exif_ifd + 2 + 12 * entry_count as u32 + 4;
+
+        let mut data = magic.to_vec();
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&1u16.to_le_bytes());
+        data.extend_from_slice(&TAG_EXIF_IFD.to_le_bytes());
+        data.extend_from_slice(&4u16.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&exif_ifd.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+
+        data.extend_from_slice(&entry_count.to_le_bytes());
+        data.extend_from_slice(&TAG_DATE_TIME_ORIGINAL.to_le_bytes());
+        data.extend_from_slice(&TYPE_ASCII.to_le_bytes());
+        data.extend_from_slice(&(date.len() as u32).to_le_bytes());
+        data.extend_from_slice(&values.to_le_bytes());
+        if offset.is_some() {
+            data.extend_from_slice(&TAG_OFFSET_TIME_ORIGINAL.to_le_bytes());
+            data.extend_from_slice(&TYPE_ASCII.to_le_bytes());
+            data.extend_from_slice(&7u32.to_le_bytes());
+            data.extend_from_slice(&(values + date.len() as u32).to_le_bytes());
+        }
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(date.as_bytes());
+        if let Some(offset) = offset {
+            data.extend_from_slice(format!("{offset}\0").as_bytes());
+        }
+        data
+    }
+
+    fn jpeg(tiff: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 4, 0, 0, 0xFF, 0xE1];
+        data.extend_from_slice(&(2 + 6 + tiff.len() as u16).to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(tiff);
+        data.extend_from_slice(&[0xFF, 0xDA, 0, 2, 0xFF, 0xD9]);
+        data
+    }
+
+    fn bmff_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = (8 + payload.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn heic(tiff: &[u8]) -> Vec<u8> {
+        heic_with_base_offset(tiff, None)
+    }
+
+    fn heic_with_base_offset(tiff: &[u8], base_offset: Option<u64>) -> Vec<u8> {
+        let mut infe = vec![2, 0, 0, 0];
+        infe.extend_from_slice(&7u16.to_be_bytes());
+        infe.extend_from_slice(&0u16.to_be_bytes());
+        infe.extend_from_slice(b"Exif");
+        let mut iinf = vec![0, 0, 0, 0, 0, 1];
+        iinf.extend_from_slice(&bmff_box(b"infe", &infe));
+
+        let ftyp = bmff_box(b"ftyp", b"heic\0\0\0\0");
+        // version 0, 4-byte offsets and lengths, an 8-byte base offset if any, one item with
+        // one extent
+        let base_offset_len = if base_offset.is_some() { 8 } else { 0 };
+        let iloc_len = 8 + 4 + 2 + 2 + 2 + 2 + base_offset_len + 2 + 4 + 4;
+        let meta_len = 8 + 4 + (8 + iinf.len()) + iloc_len;
+        let item_offset = (ftyp.len() + meta_len) as u32;
+        let mut iloc = vec![0, 0, 0, 0, 0x44, if base_offset.is_some() { 0x80 } else { 0x00 }];
+        iloc.extend_from_slice(&1u16.to_be_bytes());
+        iloc.extend_from_slice(&7u16.to_be_bytes());
+        iloc.extend_from_slice(&0u16.to_be_bytes());
+        if let Some(base_offset) = base_offset {
+            iloc.extend_from_slice(&base_offset.to_be_bytes());
+        }
+        iloc.extend_from_slice(&1u16.to_be_bytes());
+        iloc.extend_from_slice(&item_offset.to_be_bytes());
+        iloc.extend_from_slice(&(10 + tiff.len() as u32).to_be_bytes());
+
+        let mut meta = vec![0, 0, 0, 0];
+        meta.extend_from_slice(&bmff_box(b"iinf", &iinf));
+        meta.extend_from_slice(&bmff_box(b"iloc", &iloc));
+
+        let mut data = ftyp;
+        data.extend_from_slice(&bmff_box(b"meta", &meta));
+        data.extend_from_slice(&6u32.to_be_bytes());
+        data.extend_from_slice(b"Exif\0\0");
+        data.extend_from_slice(tiff);
+        data
+    }
+
+    fn date_of(name: &str, data: &[u8]) -> Option<FileDate> {
+        let temp_dir = TempDir::new("test_exif").expect("Failed to create temp dir");
+        let path = temp_dir.path().join(name);
+        fs::write(&path, data).expect("Failed to write test file");
+        date_taken(&path).expect("Failed to read EXIF")
+    }
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> FileDate {
+        FileDate::Local(NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(hour, minute, second).unwrap())
+    }
+
+    #[test]
+    fn test_jpeg_date_time_original() {
+        let data = jpeg(&tiff(b"II*\0", "2024:05:12 09:30:11", None));
+        assert_eq!(date_of("photo.jpg", &data), Some(local(2024, 5, 12, 9, 30, 11)));
+    }
+
+    #[test]
+    fn test_offset_time_original_gives_an_instant() {
+        let data = jpeg(&tiff(b"II*\0", "2024:05:12 09:30:11", Some("+09:00")));
+        let expected = Utc.with_ymd_and_hms(2024, 5, 12, 0, 30, 11).unwrap();
+        assert_eq!(date_of("photo.jpg", &data), Some(FileDate::Instant(expected)));
+    }
+
+    #[test]
+    fn test_raw_and_heic_containers() {
+        let tiff_data = tiff(b"IIRO", "2023:12:31 23:59:59", None);
+        assert_eq!(date_of("photo.orf", &tiff_data), Some(local(2023, 12, 31, 23, 59, 59)));
+        assert_eq!(date_of("photo.heic", &heic(&tiff_data)), Some(local(2023, 12, 31, 23, 59, 59)));
+    }
+
+    #[test]
+    fn test_files_without_exif() {
+        assert_eq!(date_of("notes.txt", b"just some text"), None);
+        assert_eq!(date_of("blank.jpg", &jpeg(&tiff(b"II*\0", "    :  :     :  :  ", None))), None);
+        assert_eq!(date_of("truncated.jpg", &[0xFF, 0xD8, 0xFF, 0xE1, 0x10]), None);
+    }
+
+    #[test]
+    fn test_offsets_past_the_end_of_any_file() {
+        let tiff_data = tiff(b"II*\0", "2023:12:31 23:59:59", None);
+        assert_eq!(date_of("photo.heic", &heic_with_base_offset(&tiff_data, Some(0))), Some(local(2023, 12, 31, 23, 59, 59)));
+        assert_eq!(date_of("huge.heic", &heic_with_base_offset(&tiff_data, Some(u64::MAX))), None);
+
+        let mut cursor = io::Cursor::new(tiff_data);
+        let mut tiff = Tiff { reader: &mut cursor, base: u64::MAX - 4, little_endian: true };
+        assert_eq!(tiff.read_ifd(8).map(|_| ()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,107 @@
+// Finds capture dates embedded in file names, like `IMG_20240512_093011.jpg` or
+// `Screenshot 2024-05-12 at 09.30.11.png`.
+
+use std::path::Path;
+
+use chrono::NaiveDate;
+use regex::{Captures, Regex};
+
+use crate::FileDate;
+
+const REQUIRED_GROUPS: [&str; 3] = ["year", "month", "day"];
+
+// Tried in order, so more specific patterns come first. Digits are never matched in the middle
+// of a longer number.
+const BUILT_IN_PATTERNS: [&str; 5] = [
+    // Screenshot 2024-05-12 at 09.30.11 (macOS)
+    r"(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2}) at (?P<hour>\d{1,2})\.(?P<minute>\d{2})\.(?P<second>\d{2})",
+    // IMG_20240512_093011, VID_20240512_093011, PXL_20240512_093011123 (Android)
+    r"(?:^|\D)(?P<year>\d{4})(?P<month>\d{2})(?P<day>\d{2})[_-](?P<hour>\d{2})(?P<minute>\d{2})(?P<second>\d{2})",
+    // Screenshot 2024-05-12 093011, Recording 2024-05-12 09-30-11, 2024-05-12T09:30:11 (Windows, ISO 8601)
+    r"(?:^|\D)(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})[ _T](?P<hour>\d{2})[-.:]?(?P<minute>\d{2})[-.:]?(?P<second>\d{2})(?:\D|$)",
+    // IMG-20240512-WA0003 (WhatsApp), 20240512
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})(?P<month>\d{2})(?P<day>\d{2})(?:\D|$)",
+    // 2024-05-12, 2024_05_12
+    r"(?:^|\D)(?P<year>(?:19|20)\d{2})[-_](?P<month>\d{2})[-_](?P<day>\d{2})(?:\D|$)",
+];
+
+pub struct FilenamePatterns {
+    patterns: Vec<Regex>,
+}
+
+impl FilenamePatterns {
+    // User patterns are tried before the built-in ones.
+    pub fn new(custom: Vec<Regex>) -> FilenamePatterns {
+        let built_in = BUILT_IN_PATTERNS
+            .iter()
+            .map(|pattern| Regex::new(pattern).expect("built-in file name pattern is valid"));
+        FilenamePatterns { patterns: custom.into_iter().chain(built_in).collect() }
+    }
+
+    pub fn date(&self, path: &Path) -> Option<FileDate> {
+        let name = path.file_name()?.to_string_lossy();
+        self.patterns
+            .iter()
+            .filter_map(|pattern| pattern.captures(&name))
+            .find_map(|captures| to_file_date(&captures))
+    }
+}
+
+// Compiles a user pattern, which must name at least the `year`, `month` and `day` groups
+// and may also capture `hour`, `minute` and `second`.
+pub fn parse_pattern(pattern: &str) -> Result<Regex, String> {
+    let regex = Regex::new(pattern).map_err(|e| format!("Invalid file name pattern: {e}"))?;
+    let names: Vec<&str> = regex.capture_names().flatten().collect();
+    for group in REQUIRED_GROUPS {
+        if !names.contains(&group) {
+            return Err(format!("File name pattern `{pattern}` has no `(?P<{group}>...)` group"));
+        }
+    }
+    Ok(regex)
+}
+
+fn to_file_date(captures: &Captures) -> Option<FileDate> {
+    let number = |name: &str| captures.name(name).map(|value| value.as_str().parse::<u32>().ok());
+    let date = NaiveDate::from_ymd_opt(number("year")??.try_into().ok()?, number("month")??, number("day")??)?;
+    let time = date.and_hms_opt(
+        number("hour").unwrap_or(Some(0))?,
+        number("minute").unwrap_or(Some(0))?,
+        number("second").unwrap_or(Some(0))?,
+    )?;
+    Some(FileDate::Local(time))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Option<FileDate> {
+        let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+        Some(FileDate::Local(date.and_hms_opt(hour, minute, second).unwrap()))
+    }
+
+    #[test]
+    fn test_built_in_patterns() {
+        let patterns = FilenamePatterns::new(Vec::new());
+        let date = |name: &str| patterns.date(Path::new(name));
+
+        assert_eq!(date("IMG_20240512_093011.jpg"), local(2024, 5, 12, 9, 30, 11));
+        assert_eq!(date("PXL_20240512_093011123.jpg"), local(2024, 5, 12, 9, 30, 11));
+        assert_eq!(date("IMG-20240512-WA0003.jpg"), local(2024, 5, 12, 0, 0, 0));
+        assert_eq!(date("Screenshot 2024-05-12 at 09.30.11.png"), local(2024, 5, 12, 9, 30, 11));
+        assert_eq!(date("Screenshot 2024-05-12 093011.png"), local(2024, 5, 12, 9, 30, 11));
+        assert_eq!(date("notes 2024_05_12.txt"), local(2024, 5, 12, 0, 0, 0));
+        assert_eq!(date("IMG_0001.jpg"), None);
+        assert_eq!(date("IMG_20241399_093011.jpg"), None);
+    }
+
+    #[test]
+    fn test_custom_patterns() {
+        let custom = parse_pattern(r"scan-(?P<day>\d{2})\.(?P<month>\d{2})\.(?P<year>\d{4})").unwrap();
+        let patterns = FilenamePatterns::new(vec![custom]);
+
+        assert_eq!(patterns.date(Path::new("scan-12.05.2024.pdf")), local(2024, 5, 12, 0, 0, 0));
+        assert!(parse_pattern(r"(?P<year>\d{4})(?P<month>\d{2})").is_err());
+        assert!(parse_pattern(r"(?P<year>\d{4}").is_err());
+    }
+}
@@ -0,0 +1,148 @@
+use std::path::Path;
+
+use regex::{Regex, RegexBuilder};
+
+// A shell-style glob. `*` and `?` never match `/`, `**` matches across directories, and
+// `[abc]`, `[!abc]` and `{jpg,png}` work as in most shells. A pattern without a `/` is matched
+// against the file name only, otherwise against the whole path relative to the source directory.
+pub struct Glob {
+    pattern: String,
+    regex: Regex,
+    name_only: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str, case_insensitive: bool) -> Result<Glob, String> {
+        let regex = RegexBuilder::new(&to_regex(pattern)?)
+            .case_insensitive(case_insensitive)
+            .build()
+            .map_err(|e| format!("Invalid glob `{pattern}`: {e}"))?;
+        Ok(Glob { pattern: pattern.to_string(), regex, name_only: !pattern.contains('/') })
+    }
+
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    pub fn is_match(&self, relative_path: &Path) -> bool {
+        if self.name_only {
+            let name = relative_path.file_name().unwrap_or_default();
+            return self.regex.is_match(&name.to_string_lossy());
+        }
+        let components: Vec<_> = relative_path.iter().map(|component| component.to_string_lossy()).collect();
+        self.regex.is_match(&components.join("/"))
+    }
+}
+
+fn to_regex(pattern: &str) -> Result<String, String> {
+    let mut regex = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    let mut open_braces = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.next_if_eq(&'*').is_some() => {
+                if chars.next_if_eq(&'/').is_some() {
+                    regex.push_str("(?:.*/)?");
+                } else {
+                    regex.push_str(".*");
+                }
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.next_if(|&c| c == '!' || c == '^').is_some() {
+                    regex.push('^');
+                }
+                loop {
+                    match chars.next() {
+                        Some(']') => break,
+                        Some('-') => regex.push('-'),
+                        Some(c) => regex.push_str(&regex::escape(&c.to_string())),
+                        None => return Err(format!("Invalid glob `{pattern}`: unclosed `[`")),
+                    }
+                }
+                regex.push(']');
+            }
+            '{' => {
+                open_braces += 1;
+                regex.push_str("(?:");
+            }
+            ',' if open_braces > 0 => regex.push('|'),
+            '}' if open_braces > 0 => {
+                open_braces -= 1;
+                regex.push(')');
+            }
+            c => regex.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    if open_braces > 0 {
+        return Err(format!("Invalid glob `{pattern}`: unclosed `{{`"));
+    }
+    regex.push('$');
+    Ok(regex)
+}
+
+// Which files get organized. A file is organized if it matches any include pattern (or there
+// are none) and no exclude pattern.
+#[derive(Default)]
+pub struct Filter {
+    pub include: Vec<Glob>,
+    pub exclude: Vec<Glob>,
+}
+
+impl Filter {
+    // Returns why the file is left alone, if it is.
+    pub fn skip_reason(&self, relative_path: &Path) -> Option<String> {
+        if let Some(glob) = self.exclude.iter().find(|glob| glob.is_match(relative_path)) {
+            return Some(format!("excluded by `{}`", glob.pattern()));
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|glob| glob.is_match(relative_path)) {
+            return Some("not matched by any include pattern".to_string());
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern, false).expect("Invalid glob").is_match(Path::new(path))
+    }
+
+    #[test]
+    fn test_glob_matching() {
+        assert!(matches("*.jpg", "a/b/IMG_1.jpg"));
+        assert!(!matches("*.jpg", "IMG_1.jpeg"));
+        assert!(matches("*.{jpg,png}", "shot.png"));
+        assert!(matches("IMG_????.jpg", "IMG_0001.jpg"));
+        assert!(matches("[!.]*", "photo.jpg"));
+        assert!(!matches("[!.]*", ".DS_Store"));
+        assert!(matches("raw/*.cr2", "raw/a.cr2"));
+        assert!(!matches("raw/*.cr2", "raw/day1/a.cr2"));
+        assert!(matches("raw/**/*.cr2", "raw/day1/a.cr2"));
+        assert!(matches("raw/**/*.cr2", "raw/a.cr2"));
+        assert!(Glob::new("*.{jpg", false).is_err());
+        assert!(Glob::new("[abc", false).is_err());
+    }
+
+    #[test]
+    fn test_filter_reasons() {
+        let filter = Filter {
+            include: vec![Glob::new("*.jpg", true).unwrap()],
+            exclude: vec![Glob::new("desktop.ini", true).unwrap(), Glob::new("tmp/**", true).unwrap()],
+        };
+
+        assert_eq!(filter.skip_reason(Path::new("2024/IMG_1.JPG")), None);
+        assert_eq!(filter.skip_reason(Path::new("tmp/IMG_2.jpg")), Some("excluded by `tmp/**`".to_string()));
+        assert_eq!(filter.skip_reason(Path::new("Desktop.ini")), Some("excluded by `desktop.ini`".to_string()));
+        assert_eq!(
+            filter.skip_reason(Path::new("notes.txt")),
+            Some("not matched by any include pattern".to_string())
+        );
+    }
+}
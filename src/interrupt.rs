@@ -0,0 +1,28 @@
+// Ctrl-C handling for runs that move files: the first SIGINT lets the files being placed
+// finish and then stops the run, so the journal says exactly what was done. A second one
+// ends the process straight away.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// Until this is called, SIGINT ends the process as usual.
+pub fn handle_interrupts() {
+    #[cfg(unix)]
+    unsafe {
+        libc::signal(libc::SIGINT, on_interrupt as *const () as libc::sighandler_t);
+    }
+}
+
+// Whether the run should stop before the next file.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+#[cfg(unix)]
+extern "C" fn on_interrupt(_: libc::c_int) {
+    if REQUESTED.swap(true, Ordering::SeqCst) {
+        // 128 + SIGINT, as if the signal had not been caught
+        unsafe { libc::_exit(130) };
+    }
+}
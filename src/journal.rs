@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+use crate::action::Action;
+use crate::conflict::ConflictPolicy;
+use crate::plan::Operation;
+use crate::{FileDate, SortType};
+
+const JOURNAL_DIR: &str = ".dorg";
+const JOURNAL_EXTENSION: &str = "log";
+const UNDONE_EXTENSION: &str = "undone";
+
+// Dates the journal does not know the offset of are written without one.
+const LOCAL_DATE_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.f";
+
+pub struct Record {
+    pub timestamp: DateTime<Utc>,
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    // Journals written before other actions existed only hold moves.
+    pub action: Action,
+    // Journals written before runs could be resumed only hold placed files.
+    pub state: State,
+}
+
+pub enum State {
+    // Written for every file of a run before any of them is placed, so that an interrupted
+    // run can be finished. Followed by one of the other states once the file is dealt with.
+    Planned(Intent),
+    Placed,
+    Skipped,
+    Failed,
+}
+
+// What a planned file needs to be placed later on.
+pub struct Intent {
+    pub on_conflict: ConflictPolicy,
+    pub overwrite: bool,
+    pub date_source: SortType,
+    pub date: FileDate,
+}
+
+impl Record {
+    pub fn operation(&self) -> Option<Operation> {
+        let State::Planned(intent) = &self.state else {
+            return None;
+        };
+        Some(Operation {
+            source: self.source.clone(),
+            destination: self.destination.clone(),
+            overwrite: intent.overwrite,
+            date: intent.date,
+            date_source: intent.date_source,
+            action: self.action,
+        })
+    }
+}
+
+// Holds a lock on its file for as long as it is open, so that other runs can tell it is still
+// being written.
+pub struct Journal {
+    path: PathBuf,
+    file: File,
+}
+
+impl Journal {
+    pub fn create(root: &Path) -> io::Result<Journal> {
+        let dir = root.join(JOURNAL_DIR);
+        fs::create_dir_all(&dir)?;
+
+        let name = format!("journal-{}.{JOURNAL_EXTENSION}", Utc::now().format("%Y%m%dT%H%M%S%.6f"));
+        let path = dir.join(name);
+        let file = OpenOptions::new().write(true).create_new(true).open(&path)?;
+        file.try_lock()?;
+
+        Ok(Journal { path, file })
+    }
+
+    // Opens an existing journal to add to it, e.g. to finish an interrupted run.
+    pub fn open(path: &Path) -> io::Result<Journal> {
+        let file = OpenOptions::new().append(true).open(path)?;
+        file.try_lock()?;
+        Ok(Journal { path: path.to_path_buf(), file })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Writes down every operation before any of them is carried out, and waits for that to
+    // reach the disk.
+    pub fn plan(&mut self, operations: &[Operation], on_conflict: ConflictPolicy) -> io::Result<()> {
+        for operation in operations {
+            let date = match operation.date {
+                FileDate::Instant(instant) => instant.to_rfc3339(),
+                FileDate::Local(datetime) => datetime.format(LOCAL_DATE_FORMAT).to_string(),
+            };
+            let line = format!(
+                "{}\tplanned\t{}\t{}\t{}\t{date}",
+                self.line(operation.action, &operation.source, &operation.destination),
+                on_conflict.name(),
+                operation.overwrite,
+                operation.date_source.name()
+            );
+            writeln!(self.file, "{line}")?;
+        }
+        self.file.sync_data()
+    }
+
+    pub fn record(&mut self, action: Action, source: &Path, destination: &Path) -> io::Result<()> {
+        let line = self.line(action, source, destination);
+        writeln!(self.file, "{line}")?;
+        self.file.flush()
+    }
+
+    pub fn skipped(&mut self, operation: &Operation) -> io::Result<()> {
+        self.outcome(operation, "skipped")
+    }
+
+    pub fn failed(&mut self, operation: &Operation) -> io::Result<()> {
+        self.outcome(operation, "failed")
+    }
+
+    fn outcome(&mut self, operation: &Operation, state: &str) -> io::Result<()> {
+        let line = self.line(operation.action, &operation.source, &operation.destination);
+        writeln!(self.file, "{line}\t{state}")?;
+        self.file.flush()
+    }
+
+    // The fields every record starts with. Placed files are recorded with only these, as
+    // they always were.
+    fn line(&self, action: Action, source: &Path, destination: &Path) -> String {
+        format!(
+            "{}\t{}\t{}\t{}",
+            Utc::now().to_rfc3339(),
+            encode_path(source),
+            encode_path(destination),
+            action.name()
+        )
+    }
+}
+
+// The directory the journal's moves were made relative to, i.e. the parent of `.dorg`.
+pub fn root_of(journal_path: &Path) -> Option<&Path> {
+    journal_path.parent()?.parent()
+}
+
+pub fn latest(root: &Path) -> io::Result<Option<PathBuf>> {
+    Ok(all(root)?.pop())
+}
+
+// Whether a run that is still going holds the journal at `path` open.
+pub fn in_use(path: &Path) -> io::Result<bool> {
+    match File::open(path)?.try_lock_shared() {
+        Ok(()) => Ok(false),
+        Err(TryLockError::WouldBlock) => Ok(true),
+        Err(TryLockError::Error(e)) => Err(e),
+    }
+}
+
+// The journals in `root` of runs that did not get to all their files and are not going any
+// more, oldest first, with how many files are left.
+pub fn interrupted(root: &Path) -> io::Result<Vec<(PathBuf, usize)>> {
+    let mut interrupted = Vec::new();
+    for path in all(root)? {
+        if in_use(&path)? {
+            continue;
+        }
+        let left = pending(&read(&path)?).len();
+        if left > 0 {
+            interrupted.push((path, left));
+        }
+    }
+    Ok(interrupted)
+}
+
+// The planned files that were not dealt with, in the order they were planned.
+pub fn pending(records: &[Record]) -> Vec<&Record> {
+    let mut planned = Vec::new();
+    let mut index = HashMap::new();
+    for record in records {
+        match record.state {
+            // Planned again by `dorg resume`
+            State::Planned(_) => {
+                if let Some(position) = index.insert(record.source.clone(), planned.len()) {
+                    planned[position] = None;
+                }
+                planned.push(Some(record));
+            }
+            _ => {
+                if let Some(position) = index.remove(&record.source) {
+                    planned[position] = None;
+                }
+            }
+        }
+    }
+    planned.into_iter().flatten().collect()
+}
+
+// The journals in `root`, oldest first.
+fn all(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let dir = root.join(JOURNAL_DIR);
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut journals = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == JOURNAL_EXTENSION) {
+            journals.push(path);
+        }
+    }
+    journals.sort();
+    Ok(journals)
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<Record>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let record = parse_record(&line).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Malformed journal record on line {}", index + 1),
+            )
+        })?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+pub fn mark_undone(path: &Path) -> io::Result<()> {
+    let mut undone = path.as_os_str().to_owned();
+    undone.push(".");
+    undone.push(UNDONE_EXTENSION);
+    fs::rename(path, undone)
+}
+
+fn parse_record(line: &str) -> Option<Record> {
+    let mut fields = line.split('\t');
+    let timestamp = DateTime::parse_from_rfc3339(fields.next()?).ok()?.with_timezone(&Utc);
+    let source = decode_path(fields.next()?)?;
+    let destination = decode_path(fields.next()?)?;
+    let action = match fields.next() {
+        Some(action) => Action::parse(action)?,
+        None => Action::Move,
+    };
+    let state = match fields.next() {
+        None => State::Placed,
+        Some("skipped") => State::Skipped,
+        Some("failed") => State::Failed,
+        Some("planned") => {
+            let on_conflict = ConflictPolicy::parse(fields.next()?)?;
+            let overwrite = fields.next()?.parse().ok()?;
+            let date_source = SortType::parse(fields.next()?)?;
+            let date = fields.next()?;
+            let date = match DateTime::parse_from_rfc3339(date) {
+                Ok(instant) => FileDate::Instant(instant.with_timezone(&Utc)),
+                Err(_) => FileDate::Local(NaiveDateTime::parse_from_str(date, LOCAL_DATE_FORMAT).ok()?),
+            };
+            State::Planned(Intent { on_conflict, overwrite, date_source, date })
+        }
+        Some(_) => return None,
+    };
+    if fields.next().is_some() {
+        return None;
+    }
+    Some(Record { timestamp, source, destination, action, state })
+}
+
+// Paths are stored one per field, so tabs, newlines and backslashes are escaped.
+// Bytes that are not valid UTF-8 are written as `\xNN` so that no name is lost.
+fn encode_path(path: &Path) -> String {
+    let bytes = path.as_os_str().as_encoded_bytes();
+    let mut encoded = String::with_capacity(bytes.len());
+
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => encoded.push_str("\\\\"),
+                '\t' => encoded.push_str("\\t"),
+                '\n' => encoded.push_str("\\n"),
+                '\r' => encoded.push_str("\\r"),
+                c => encoded.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            encoded.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    encoded
+}
+
+fn decode_path(encoded: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+        match chars.next()? {
+            '\\' => bytes.push(b'\\'),
+            't' => bytes.push(b'\t'),
+            'n' => bytes.push(b'\n'),
+            'r' => bytes.push(b'\r'),
+            'x' => {
+                let hex: String = chars.by_ref().take(2).collect();
+                bytes.push(u8::from_str_radix(&hex, 16).ok()?);
+            }
+            _ => return None,
+        }
+    }
+    Some(PathBuf::from(os_string_from_bytes(bytes)))
+}
+
+#[cfg(unix)]
+fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+fn os_string_from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_journal_round_trip() {
+        let temp_dir = TempDir::new("test_journal").expect("Failed to create temp dir");
+        let source = PathBuf::from("/photos/tab\there/new\nline\\IMG_0001.jpg");
+        let destination = temp_dir.path().join("2024").join("5").join("IMG_0001.jpg");
+
+        let mut journal = Journal::create(temp_dir.path()).expect("Failed to create journal");
+        journal.record(Action::Copy, &source, &destination).expect("Failed to write record");
+
+        assert_eq!(latest(temp_dir.path()).unwrap().as_deref(), Some(journal.path()));
+        assert_eq!(root_of(journal.path()), Some(temp_dir.path()));
+
+        let records = read(journal.path()).expect("Failed to read journal");
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].source, source);
+        assert_eq!(records[0].destination, destination);
+        assert_eq!(records[0].action, Action::Copy);
+
+        let old_record = parse_record("2024-05-12T09:30:11+00:00\t/a.jpg\t/2024/5/a.jpg").expect("Failed to parse");
+        assert_eq!(old_record.action, Action::Move);
+    }
+
+    #[test]
+    fn test_planned_files_stay_pending_until_dealt_with() {
+        let temp_dir = TempDir::new("test_journal").expect("Failed to create temp dir");
+        let operation = |name: &str, date| Operation {
+            source: PathBuf::from("/in").join(name),
+            destination: PathBuf::from("/out/2024/5").join(name),
+            overwrite: name == "c.jpg",
+            date,
+            date_source: SortType::Exif,
+            action: Action::Copy,
+        };
+        let local = NaiveDateTime::parse_from_str("2024-05-12T09:30:11", LOCAL_DATE_FORMAT).unwrap();
+        let instant = DateTime::parse_from_rfc3339("2024-05-12T09:30:11.5+02:00").unwrap().with_timezone(&Utc);
+        let operations = [
+            operation("a.jpg", FileDate::Local(local)),
+            operation("b.jpg", FileDate::Instant(instant)),
+            operation("c.jpg", FileDate::Local(local)),
+            operation("d.jpg", FileDate::Local(local)),
+        ];
+
+        let mut journal = Journal::create(temp_dir.path()).expect("Failed to create journal");
+        journal.plan(&operations, ConflictPolicy::KeepNewer).expect("Failed to write plan");
+        journal.record(Action::Copy, &operations[0].source, &operations[0].destination).unwrap();
+        journal.skipped(&operations[2]).unwrap();
+        journal.failed(&operations[3]).unwrap();
+        // Still being written
+        assert!(interrupted(temp_dir.path()).unwrap().is_empty());
+
+        let path = journal.path().to_path_buf();
+        drop(journal);
+        let runs = interrupted(temp_dir.path()).expect("Failed to read journals");
+        assert_eq!(runs, [(path.clone(), 1)]);
+        let records = read(&path).unwrap();
+        let left = pending(&records);
+        assert_eq!(left[0].source, operations[1].source);
+        let State::Planned(intent) = &left[0].state else { panic!("Only planned files are pending") };
+        assert_eq!(intent.on_conflict, ConflictPolicy::KeepNewer);
+        assert_eq!(left[0].operation().unwrap().date, FileDate::Instant(instant));
+
+        let mut journal = Journal::open(&path).expect("Failed to open journal");
+        journal.record(Action::Copy, &operations[1].source, &operations[1].destination).unwrap();
+        drop(journal);
+        assert!(interrupted(temp_dir.path()).unwrap().is_empty());
+        let records = read(&path).unwrap();
+        assert!(matches!(&records[2].state, State::Planned(intent) if intent.overwrite));
+    }
+}
@@ -1,193 +1,1008 @@
-use std::error::Error;
-use std::fs::{DirEntry, Metadata};
-use std::path::{Component, Path, PathBuf};
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::path::{self, Path, PathBuf};
 use std::{fmt, fs, io};
-use std::time::SystemTime;
-use chrono::{DateTime, Datelike, Utc};
+use std::time::{Duration, SystemTime};
+use chrono::{DateTime, Datelike, NaiveDateTime, Utc};
 
+mod action;
+mod bmff;
+mod category;
+mod conflict;
+mod error;
+mod exif;
+mod filename;
+mod filter;
+mod interrupt;
+mod journal;
+mod organizer;
+mod output;
+mod parallel;
+mod plan;
+mod rule;
+mod settings;
+mod sniff;
+mod template;
+mod video;
+mod walk;
+mod watch;
+mod zone;
+
+use conflict::{Occupant, Resolution};
+use error::IoOperation::*;
+use parallel::DirectoryCache;
+use journal::{Record, State};
+use organizer::Collector;
+use rule::{Check, Rule, RuleAction, Subject};
+
+pub use category::Categories;
+
+pub use action::Action;
+pub use conflict::ConflictPolicy;
+pub use error::{DorgError, FailureKind, IoContext, IoOperation, MetadataError};
+pub use filename::FilenamePatterns;
+pub use filter::{Filter, Glob};
+pub use interrupt::handle_interrupts;
+pub use journal::Journal;
+pub use organizer::{Event, Failed, Observer, Organizer, Placed, Report};
+pub use output::{Output, Printer};
+pub use plan::{Failure, Operation, Plan, Skipped};
+pub use template::{Template, TemplateContext, TemplateError};
+pub use walk::{Candidate, Walker};
+pub use watch::{WatchOptions, Watcher};
+pub use zone::Zone;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SortType {
-    Created, Modified
+    Created, Modified, Exif, Video, Filename
 }
 
-pub enum Mode {
-    Month, Day
-}
+impl SortType {
+    pub fn parse(sort_type: &str) -> Option<SortType> {
+        match sort_type {
+            "created" => Some(SortType::Created),
+            "modified" => Some(SortType::Modified),
+            "exif" => Some(SortType::Exif),
+            "video" => Some(SortType::Video),
+            "filename" => Some(SortType::Filename),
+            _ => None,
+        }
+    }
 
-#[derive(Debug)]
-pub enum MetadataError {
-    CreationTimeUnavailable,
-    IoError(io::Error)
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortType::Created => "created",
+            SortType::Modified => "modified",
+            SortType::Exif => "exif",
+            SortType::Video => "video",
+            SortType::Filename => "filename",
+        }
+    }
+
+    // Sources that read the file's contents, which many files will not have.
+    pub fn is_content_based(&self) -> bool {
+        matches!(self, SortType::Exif | SortType::Video | SortType::Filename)
+    }
 }
 
+// A file's date either as an exact instant, or as a wall-clock time with no known offset,
+// like an EXIF DateTimeOriginal without OffsetTimeOriginal, which is used as-is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileDate {
+    Instant(DateTime<Utc>),
+    Local(NaiveDateTime),
+}
 
-impl fmt::Display for MetadataError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+impl FileDate {
+    pub fn in_zone(&self, timezone: Zone) -> NaiveDateTime {
         match self {
-            MetadataError::CreationTimeUnavailable => write!(f, "Creation time is unavailable"),
-            MetadataError::IoError(e) => write!(f, "IO error: {}", e),
+            FileDate::Instant(instant) => timezone.to_naive(*instant),
+            FileDate::Local(datetime) => *datetime,
         }
     }
 }
 
-impl Error for MetadataError {
-    fn source(&self) -> Option<&(dyn Error + 'static)> {
-        match self {
-            MetadataError::IoError(e) => Some(e),
+pub enum Mode {
+    Month, Day,
+    // A directory per type category, optionally followed by the date directories, e.g. `Images/2024/5`.
+    Type(Option<Bucket>),
+    Template(Template),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bucket {
+    Month, Day
+}
+
+impl Mode {
+    pub fn parse(mode: &str) -> Option<Mode> {
+        match mode {
+            "day" => Some(Mode::Day),
+            "month" => Some(Mode::Month),
+            "type" => Some(Mode::Type(None)),
+            "type/month" => Some(Mode::Type(Some(Bucket::Month))),
+            "type/day" => Some(Mode::Type(Some(Bucket::Day))),
             _ => None,
         }
     }
 }
 
-impl From<io::Error> for MetadataError {
-    fn from(error: io::Error) -> Self {
-        MetadataError::IoError(error)
+impl Bucket {
+    fn directories(&self, datetime: NaiveDateTime) -> PathBuf {
+        let month = PathBuf::from(datetime.year().to_string()).join(datetime.month().to_string());
+        match self {
+            Bucket::Month => month,
+            Bucket::Day => month.join(datetime.day().to_string()),
+        }
+    }
+}
+
+pub enum Command {
+    Organize(Config),
+    Undo(Option<PathBuf>),
+    Resume(Option<PathBuf>),
+    // A file, and the options it would be organized with from the directory it is in.
+    Explain(PathBuf, Config),
+    // Organizes files as they appear in the configured directory.
+    Watch(Config, WatchOptions),
+}
+
+impl Command {
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Command, DorgError> {
+        Command::parse(args).map_err(DorgError::Usage)
+    }
+
+    fn parse(args: impl Iterator<Item = String>) -> Result<Command, String> {
+        let mut args = args.peekable();
+        let program = args.next();
+
+        if let Some(command) = args.next_if(|arg| arg == "undo" || arg == "resume") {
+            let target = args.next().map(PathBuf::from);
+            if args.next().is_some() {
+                return Err("Unknown argument".into());
+            }
+            return Ok(if command == "undo" { Command::Undo(target) } else { Command::Resume(target) });
+        }
+
+        if args.peek().is_some_and(|arg| arg == "explain") {
+            args.next();
+            let file = PathBuf::from(args.next().ok_or("File not specified")?);
+            let directory = match file.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            let args = program.into_iter().chain([directory.to_string_lossy().into_owned()]).chain(args);
+            return Ok(Command::Explain(file, Config::parse(args)?));
+        }
+
+        if args.peek().is_some_and(|arg| arg == "watch") {
+            args.next();
+            let mut options = WatchOptions::default();
+            let mut rest = Vec::new();
+            for arg in args {
+                if arg == "--poll" {
+                    options.poll = true;
+                } else if let Some(seconds) = arg.strip_prefix("--settle=") {
+                    let seconds: f64 = seconds.parse().map_err(|_| "Invalid settle time")?;
+                    options.settle = Duration::try_from_secs_f64(seconds).map_err(|_| "Invalid settle time")?;
+                } else {
+                    rest.push(arg);
+                }
+            }
+            return Ok(Command::Watch(Config::parse(program.into_iter().chain(rest))?, options));
+        }
+
+        Config::parse(program.into_iter().chain(args)).map(Command::Organize)
     }
 }
 
 pub struct Config {
     pub directory_path: PathBuf,
+    pub destination: PathBuf,
     pub recursive: bool,
+    pub max_depth: Option<usize>,
     pub mode: Mode,
-    pub sort_type: SortType,
+    // Tried in order for each file until one of them yields a date.
+    pub date_sources: Vec<SortType>,
+    pub filename_patterns: FilenamePatterns,
+    pub filter: Filter,
+    pub categories: Categories,
+    // Recognise file types by their contents rather than their extension.
+    pub sniff: bool,
+    pub fix_extensions: bool,
+    pub timezone: Zone,
+    pub dry_run: bool,
+    pub on_conflict: ConflictPolicy,
+    pub action: Action,
+    // Tried in order for each file, the first one that matches overrides the options above.
+    pub rules: Vec<Rule>,
+    // How many files are looked at and moved at the same time.
+    pub jobs: usize,
+    // Carry on with the other files when one fails, instead of stopping the run.
+    pub keep_going: bool,
+    pub output: Output,
 }
 
 impl Config {
-    pub fn build(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
+    // Every option at its default, organizing the directory in place.
+    pub fn new(directory_path: PathBuf) -> Config {
+        Config {
+            destination: directory_path.clone(),
+            directory_path,
+            recursive: false,
+            max_depth: None,
+            mode: Mode::Month,
+            date_sources: vec![SortType::Created, SortType::Modified],
+            filename_patterns: FilenamePatterns::new(Vec::new()),
+            filter: Filter::default(),
+            categories: Categories::new(),
+            sniff: false,
+            fix_extensions: false,
+            timezone: Zone::Local,
+            dry_run: false,
+            on_conflict: ConflictPolicy::Rename,
+            action: Action::Move,
+            rules: Vec::new(),
+            jobs: 1,
+            keep_going: false,
+            output: Output::Text,
+        }
+    }
+
+    pub fn build(args: impl Iterator<Item = String>) -> Result<Config, DorgError> {
+        Config::parse(args).map_err(DorgError::Usage)
+    }
+
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Config, String> {
         args.next();
 
         let directory_path = match args.next() {
             Some(arg) => PathBuf::from(arg),
-            None => return Err("Directory not specified"),
+            None => return Err("Directory not specified".into()),
         };
+        let (args, rules) = with_settings(&directory_path, args.collect())?;
+
+        let directory_path = path::absolute(directory_path).map_err(|_| "Invalid directory")?;
+        let mut config = Config { rules, ..Config::new(directory_path) };
+        let mut destination = None;
+        let mut in_place = false;
+        let mut filename_patterns = Vec::new();
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        let mut ignore_case = false;
 
-        let mut recursive = false;
-        let mut mode = Mode::Month;
-        let mut sort_type = SortType::Created;
-
-        while let Some(arg) = args.next() {
-            match arg.as_str() {
-                "-r" => recursive = true,
-                arg if arg.starts_with("-mode=") => {
-                    let mode_str = &arg["-mode=".len()..];
-                    mode = match mode_str {
-                        "day" => Mode::Day,
-                        "month" => Mode::Month,
-                        _ => return Err("Invalid mode"),
-                    }
-                },
-                arg if arg.starts_with("-sort=") => {
-                    let sort_str = &arg["-sort=".len()..];
-                    sort_type = match sort_str {
-                        "created" => SortType::Created,
-                        "modified" => SortType::Modified,
-                        _ => return Err("Invalid sort type"),                  
+        let mut args = args.into_iter();
+        while let Some((arg, origin)) = args.next() {
+            let mut apply = || -> Result<(), String> {
+                match arg.as_str() {
+                    "-r" => config.recursive = true,
+                    "--dry-run" => config.dry_run = true,
+                    "--in-place" => in_place = true,
+                    "--ignore-case" => ignore_case = true,
+                    "--sniff" => config.sniff = true,
+                    "--keep-going" => config.keep_going = true,
+                    "-j" => {
+                        let (jobs, _) = args.next().ok_or("Number of jobs not specified")?;
+                        config.jobs = parse_jobs(&jobs)?;
+                    }
+                    arg if arg.starts_with("--jobs=") => config.jobs = parse_jobs(&arg["--jobs=".len()..])?,
+                    arg if arg.starts_with("-j") => config.jobs = parse_jobs(&arg["-j".len()..])?,
+                    "--fix-extensions" => {
+                        config.sniff = true;
+                        config.fix_extensions = true;
                     }
+                    arg if arg.starts_with("--template=") => {
+                        let pattern = &arg["--template=".len()..];
+                        let template = Template::parse(pattern).map_err(|e| format!("Invalid template: {e}"))?;
+                        config.mode = Mode::Template(template);
+                    }
+                    arg if arg.starts_with("--dest=") => {
+                        destination = Some(PathBuf::from(&arg["--dest=".len()..]));
+                    }
+                    arg if arg.starts_with("--max-depth=") => {
+                        let depth_str = &arg["--max-depth=".len()..];
+                        config.max_depth = Some(depth_str.parse().map_err(|_| "Invalid max depth")?);
+                        config.recursive = true;
+                    }
+                    arg if arg.starts_with("-mode=") => {
+                        let mode_str = &arg["-mode=".len()..];
+                        config.mode = Mode::parse(mode_str).ok_or("Invalid mode")?;
+                    },
+                    arg if arg.starts_with("--type-group=") => {
+                        let (name, extensions) = category::parse_group(&arg["--type-group=".len()..])?;
+                        config.categories.set(&name, extensions);
+                    }
+                    arg if arg.starts_with("-sort=") => {
+                        let sort_str = &arg["-sort=".len()..];
+                        let sort_type = SortType::parse(sort_str).ok_or("Invalid sort type")?;
+                        config.date_sources = vec![sort_type];
+                        // Files without embedded dates fall back to the filesystem times
+                        if sort_type.is_content_based() {
+                            config.date_sources.extend([SortType::Created, SortType::Modified]);
+                        }
+                    }
+                    arg if arg.starts_with("--date-source=") => {
+                        let sources_str = &arg["--date-source=".len()..];
+                        config.date_sources = sources_str
+                            .split(',')
+                            .map(|source| SortType::parse(source).ok_or(format!("Invalid date source: {source}")))
+                            .collect::<Result<_, _>>()?;
+                    }
+                    arg if arg.starts_with("--filename-pattern=") => {
+                        let pattern = &arg["--filename-pattern=".len()..];
+                        filename_patterns.push(filename::parse_pattern(pattern)?);
+                    }
+                    arg if arg.starts_with("--include=") => {
+                        include.push((arg["--include=".len()..].to_string(), origin.clone()));
+                    }
+                    arg if arg.starts_with("--exclude=") => {
+                        exclude.push((arg["--exclude=".len()..].to_string(), origin.clone()));
+                    }
+                    arg if arg.starts_with("--tz=") => {
+                        let zone_str = &arg["--tz=".len()..];
+                        config.timezone =
+                            Zone::parse(zone_str).ok_or_else(|| format!("Invalid time zone: {zone_str}"))?;
+                    }
+                    arg if arg.starts_with("--on-conflict=") => {
+                        let policy_str = &arg["--on-conflict=".len()..];
+                        config.on_conflict = ConflictPolicy::parse(policy_str).ok_or("Invalid conflict policy")?;
+                    }
+                    arg if arg.starts_with("--output=") => {
+                        let output_str = &arg["--output=".len()..];
+                        config.output = Output::parse(output_str).ok_or("Invalid output format")?;
+                    }
+                    arg if arg.starts_with("--action=") => {
+                        let action_str = &arg["--action=".len()..];
+                        config.action = Action::parse(action_str).ok_or("Invalid action")?;
+                    }
+                    _ => return Err("Unknown argument".into()),
                 }
-                _ => return Err("Unknown argument"),
+                Ok(())
+            };
+            apply().map_err(|e| located(&origin, e))?;
+        }
+
+        // Compiled once all arguments are read, since --ignore-case may come after the patterns
+        let compile = |patterns: Vec<Located>| -> Result<Vec<Glob>, String> {
+            patterns
+                .iter()
+                .map(|(pattern, origin)| Glob::new(pattern, ignore_case).map_err(|e| located(origin, e)))
+                .collect()
+        };
+        config.filter = Filter { include: compile(include)?, exclude: compile(exclude)? };
+        config.filename_patterns = FilenamePatterns::new(filename_patterns);
+
+        config.destination = match (destination, in_place) {
+            (Some(_), true) => return Err("--dest and --in-place cannot be used together".into()),
+            (Some(destination), false) => path::absolute(destination).map_err(|_| "Invalid destination")?,
+            // Never wherever the shell happens to be
+            (None, _) => config.directory_path.clone(),
+        };
+        Ok(config)
+    }
+
+    // How many levels of subdirectories to descend into.
+    pub fn walk_depth(&self) -> usize {
+        match (self.recursive, self.max_depth) {
+            (false, _) => 0,
+            (true, Some(max_depth)) => max_depth,
+            (true, None) => usize::MAX,
+        }
+    }
+}
+
+// Names the configuration file and line an argument came from, if it came from one.
+fn located(origin: &Option<String>, error: String) -> String {
+    match origin {
+        Some(origin) => format!("Invalid configuration {origin}: {error}"),
+        None => error,
+    }
+}
+
+fn parse_jobs(jobs: &str) -> Result<usize, String> {
+    match jobs.parse() {
+        Ok(0) | Err(_) => Err("Invalid number of jobs".into()),
+        Ok(jobs) => Ok(jobs),
+    }
+}
+
+// An argument, and where in the configuration file it was set if it came from there.
+type Located = (String, Option<String>);
+
+// Puts the arguments from the configuration file, if there is one, before those given on the
+// command line, leaving out any option the command line sets itself. Each argument from the file
+// comes with the file and line it was set on. Also returns the file's rules.
+fn with_settings(directory_path: &Path, args: Vec<String>) -> Result<(Vec<Located>, Vec<Rule>), String> {
+    let mut config_path = None;
+    let mut profile = None;
+    let mut cli_args = Vec::new();
+    for arg in args {
+        if let Some(path) = arg.strip_prefix("--config=") {
+            config_path = Some(PathBuf::from(path));
+        } else if let Some(name) = arg.strip_prefix("--profile=") {
+            profile = Some(name.to_string());
+        } else {
+            cli_args.push(arg);
+        }
+    }
+
+    let config_path = match config_path {
+        Some(path) if !path.is_file() => return Err(format!("Configuration file {:?} not found", path)),
+        Some(path) => Some(path),
+        None => settings::find(directory_path),
+    };
+    let Some(config_path) = config_path else {
+        return match profile {
+            Some(profile) => Err(format!("No configuration file to read profile `{profile}` from")),
+            None => Ok((cli_args.into_iter().map(|arg| (arg, None)).collect(), Vec::new())),
+        };
+    };
+
+    // --dest and --in-place are two ways of setting the same thing, as are -j and --jobs
+    let option = |arg: &str| match arg.split('=').next().unwrap_or_default() {
+        "--in-place" => "--dest".to_string(),
+        name if name.starts_with("-j") => "--jobs".to_string(),
+        name => name.to_string(),
+    };
+    let overridden: HashSet<String> = cli_args.iter().map(|arg| option(arg)).collect();
+    let settings = settings::load(&config_path, profile.as_deref())?;
+    let mut args: Vec<Located> = settings
+        .args
+        .into_iter()
+        .filter(|(arg, _)| !overridden.contains(&option(arg)))
+        .map(|(arg, line)| (arg, Some(format!("{:?}, line {line}", config_path))))
+        .collect();
+    args.extend(cli_args.into_iter().map(|arg| (arg, None)));
+    Ok((args, settings.rules))
+}
+
+pub fn plan(config: &Config) -> Result<Plan, DorgError> {
+    let mut plan = Plan::new(config.on_conflict, config.action);
+    process_directory(config, &mut plan)?;
+    Ok(plan)
+}
+
+// Carries out the plan with up to `jobs` files being placed at once. The whole plan is written
+// to the journal first, and then each result is reported and recorded in the order of the
+// plan. A file that cannot be placed stops the run, unless `keep_going` is set.
+pub fn execute(
+    plan: &Plan,
+    journal: &mut Journal,
+    jobs: usize,
+    keep_going: bool,
+    observer: &mut dyn Observer,
+) -> Result<(), DorgError> {
+    journal.plan(&plan.operations, plan.on_conflict).context(Write, journal.path())?;
+    // Each directory is only created once a file goes into it, so a run that stops early
+    // leaves no empty ones behind
+    let directories = DirectoryCache::default();
+    // Files going to the same place are placed in the order of the plan, so that the last one
+    // wins with `--on-conflict overwrite`
+    parallel::for_each_ordered_by_key(
+        &plan.operations,
+        jobs,
+        |operation| &operation.destination,
+        |operation| match interrupt::requested() {
+            true => Err(DorgError::Interrupted),
+            false => place_file(operation, plan.on_conflict, &directories),
+        },
+        |operation, placement| {
+            match placement {
+                Ok(Placement::Placed(destination)) => {
+                    let recorded = journal.record(operation.action, &operation.source, &destination);
+                    recorded.context(Write, journal.path())?;
+                    observer.event(Event::Moved { operation, destination: &destination });
+                }
+                Ok(Placement::Skipped(reason)) => {
+                    journal.skipped(operation).context(Write, journal.path())?;
+                    observer.event(Event::Skipped { path: &operation.source, reason: &reason });
+                }
+                // The files from here on stay planned, for `dorg resume`
+                Err(DorgError::Interrupted) => return Err(DorgError::Interrupted),
+                Err(error) => {
+                    journal.failed(operation).context(Write, journal.path())?;
+                    observer.event(Event::Failed { path: &operation.source, error: &error });
+                    if !keep_going {
+                        return Err(error);
+                    }
+                }
+            }
+            Ok(())
+        },
+    )
+}
+
+// Works out which rule, if any, a file matches and why, and what would be done with it.
+pub fn explain(file: &Path, config: &Config) -> Result<Explanation, DorgError> {
+    let path = path::absolute(file).context(Stat, file)?;
+    let relative_path = path.strip_prefix(&config.directory_path).unwrap_or(&path).to_path_buf();
+    let metadata = fs::symlink_metadata(&path).context(Stat, &path)?;
+    let mut explanation = Explanation { path: path.clone(), skip_reason: None, rules: Vec::new(), plan: None };
+
+    if let Some(reason) = skip_reason(config, &relative_path) {
+        explanation.skip_reason = Some(reason);
+        return Ok(explanation);
+    }
+
+    let subject = Subject::new(&path, &relative_path, &metadata);
+    for rule in &config.rules {
+        let checks = rule.checks(&subject).context(Read, &path)?;
+        let matched = checks.iter().all(|check| check.matched);
+        explanation.rules.push((rule.name.clone(), checks));
+        if matched {
+            break;
+        }
+    }
+
+    let mut plan = Plan::new(config.on_conflict, config.action);
+    plan_file(&Candidate { path, relative_path, depth: 0 }, config, &mut plan)?;
+    explanation.plan = Some(plan);
+    Ok(explanation)
+}
+
+// What `explain` found out about a file, shown as the rules that were tried and the plan.
+pub struct Explanation {
+    path: PathBuf,
+    // Why the file is not organized at all, if it is not
+    skip_reason: Option<String>,
+    // The rules tried in order, up to the first that matches, with each of their conditions
+    rules: Vec<(String, Vec<Check>)>,
+    plan: Option<Plan>,
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(reason) = &self.skip_reason {
+            return writeln!(f, "{:?} is not organized: {reason}", self.path);
+        }
+        let mut matched = false;
+        for (name, checks) in &self.rules {
+            matched = checks.iter().all(|check| check.matched);
+            writeln!(f, "Rule `{name}` {}", if matched { "matches" } else { "does not match" })?;
+            for check in checks {
+                let mark = if check.matched { "yes" } else { "no" };
+                writeln!(f, "  {mark}: {} ({})", check.condition, check.actual)?;
             }
         }
+        if !matched {
+            writeln!(f, "No rule matches, the global options apply")?;
+        }
+        match &self.plan {
+            Some(plan) => write!(f, "{plan}"),
+            None => Ok(()),
+        }
+    }
+}
 
-        Ok(Config { directory_path, recursive, mode, sort_type })
+// Organizes each new file once it has settled, until the process is stopped. All files
+// organized in one session are recorded in the same journal.
+fn watch(config: &Config, mut watcher: Watcher, observer: &mut dyn Observer) -> Result<(), DorgError> {
+    let mut journal = None;
+    // Where files were put inside the watched directory, so that they are not picked up
+    // again. Each one is forgotten once the watcher has reported it.
+    let mut placed = HashSet::new();
+    loop {
+        for path in watcher.step()? {
+            if placed.remove(&path) {
+                continue;
+            }
+            if let Err(e) = organize_new_file(&path, config, &mut journal, &mut placed, observer) {
+                observer.event(Event::Failed { path: &path, error: &e });
+            }
+        }
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    process_directory(&config)?;
+fn organize_new_file(
+    path: &Path,
+    config: &Config,
+    journal: &mut Option<Journal>,
+    placed: &mut HashSet<PathBuf>,
+    observer: &mut dyn Observer,
+) -> Result<(), DorgError> {
+    let relative_path = path.strip_prefix(&config.directory_path).unwrap_or(path).to_path_buf();
+    if let Some(reason) = skip_reason(config, &relative_path) {
+        observer.event(Event::Skipped { path, reason: &reason });
+        return Ok(());
+    }
+
+    let depth = relative_path.components().count().saturating_sub(1);
+    let mut plan = Plan::new(config.on_conflict, config.action);
+    plan_file(&Candidate { path: path.to_path_buf(), relative_path, depth }, config, &mut plan)?;
+    for skipped in &plan.skipped {
+        observer.event(Event::Skipped { path: &skipped.path, reason: &skipped.reason });
+    }
+    for operation in &plan.operations {
+        observer.event(Event::Planned(operation));
+    }
+    if plan.is_empty() || config.dry_run {
+        return Ok(());
+    }
+
+    let journal = match journal {
+        Some(journal) => journal,
+        None => {
+            let created = Journal::create(&config.destination).context(Write, &config.destination)?;
+            observer.event(Event::Journal(created.path()));
+            journal.insert(created)
+        }
+    };
+    execute(&plan, journal, config.jobs, false, observer)?;
+    // Files put where the watcher does not look are never reported, so are not kept track of
+    let watched = |destination: &Path| match destination.strip_prefix(&config.directory_path) {
+        Ok(relative) => relative.components().count().saturating_sub(1) <= config.walk_depth(),
+        Err(_) => false,
+    };
+    let reported = plan.operations.iter().filter(|operation| watched(&operation.destination));
+    placed.extend(reported.map(|operation| operation.destination.clone()));
     Ok(())
 }
 
-fn process_directory(config: &Config) -> Result<(), Box<dyn Error>> {
-    let entries = fs::read_dir(&config.directory_path)?;
+// Reverses a run recorded in a journal. `target` may be a journal file or the directory
+// a run was made in; without one, the latest journal in the current directory is used.
+pub fn undo(target: Option<&Path>, observer: &mut dyn Observer) -> Result<(), DorgError> {
+    let no_journal = || DorgError::Journal("No journal found to undo".to_string());
+    let journal_path = match target {
+        Some(path) if path.is_file() => path.to_path_buf(),
+        Some(path) => journal::latest(path).context(ReadDir, path)?.ok_or_else(no_journal)?,
+        None => {
+            let current_dir = std::env::current_dir().context(Stat, Path::new("."))?;
+            journal::latest(&current_dir).context(ReadDir, &current_dir)?.ok_or_else(no_journal)?
+        }
+    };
+    let root = journal::root_of(&journal_path)
+        .ok_or_else(|| DorgError::Journal(format!("{:?} is not in a journal directory", journal_path)))?
+        .to_path_buf();
+    not_in_use(&journal_path)?;
 
-    for entry in entries {
-        let entry = entry?;
-        if entry.file_type()?.is_dir() {
-            if config.recursive {
-                process_directory(config)?;
+    let records = journal::read(&journal_path).context(Read, &journal_path)?;
+    // An interrupted run may have placed files without getting to record them
+    let unrecorded = journal::pending(&records)
+        .into_iter()
+        .filter(|record| action::is_done(record.action, &record.source, &record.destination));
+    let placed: Vec<&Record> =
+        records.iter().filter(|record| matches!(record.state, State::Placed)).chain(unrecorded).collect();
+    for record in placed.into_iter().rev() {
+        // Copies and links are removed, the files they were made from never left
+        if record.action.keeps_source() {
+            if record.destination.symlink_metadata().is_err() {
+                observer.event(Event::Skipped { path: &record.destination, reason: "it no longer exists" });
+                continue;
             }
+            fs::remove_file(&record.destination).context(Remove, &record.destination)?;
+        } else if !record.destination.exists() || record.source.exists() {
+            let reason = format!("it is no longer where it was moved to on {}", record.timestamp.to_rfc3339());
+            observer.event(Event::Skipped { path: &record.source, reason: &reason });
+            continue;
         } else {
-            move_file(entry, &config.mode, &config.sort_type)?;
+            if let Some(parent) = record.source.parent() {
+                fs::create_dir_all(parent).context(Mkdir, parent)?;
+            }
+            conflict::rename_no_clobber(&record.destination, &record.source).context(Rename, &record.destination)?;
+        }
+        observer.event(Event::Undone { source: &record.source, destination: &record.destination, action: record.action });
+
+        if let Some(parent) = record.destination.parent() {
+            remove_empty_dirs(parent, &root);
         }
     }
 
+    journal::mark_undone(&journal_path).context(Rename, &journal_path)?;
     Ok(())
 }
 
-
-fn move_file(file: DirEntry, mode: &Mode, sort_type: &SortType) -> Result<(), Box<dyn Error>> {
-    let original_path = file.path();
-    let parent_dir = get_parent_dir(&original_path)
-        .ok_or("Error getting the parent directory")?;
-
-    let metadata = file.metadata()?;
-    let creation_time = match sort_type {
-        SortType::Created => get_creation_time(metadata)?,
-        SortType::Modified => get_modification_time(metadata)?,
+// Finishes a run that was interrupted. Files it placed without getting to record them are
+// recorded now, and the others are placed as planned. Files that cannot be placed are reported
+// and left for the end, like with `--keep-going`.
+pub fn resume(target: Option<&Path>, observer: &mut dyn Observer) -> Result<Report, DorgError> {
+    let latest_interrupted = |dir: &Path| {
+        let latest = journal::interrupted(dir).context(ReadDir, dir)?.pop().map(|(path, _)| path);
+        latest.ok_or_else(|| DorgError::Journal("No interrupted run found to resume".to_string()))
+    };
+    let journal_path = match target {
+        Some(path) if path.is_file() => path.to_path_buf(),
+        Some(path) => latest_interrupted(path)?,
+        None => latest_interrupted(&std::env::current_dir().context(Stat, Path::new("."))?)?,
     };
-    let (year, month, day) = get_year_month_day(creation_time);
 
-    let new_dir = match mode {
-        Mode::Month => parent_dir.join(year.to_string()).join(month.to_string()),
-        Mode::Day => parent_dir.join(year.to_string()).join(month.to_string()).join(day.to_string()),
+    not_in_use(&journal_path)?;
+    let records = journal::read(&journal_path).context(Read, &journal_path)?;
+    let left = journal::pending(&records);
+    let Some(State::Planned(intent)) = left.first().map(|record| &record.state) else {
+        return Err(DorgError::Journal(format!("{:?} has no files left to place", journal_path)));
     };
+    // A run has one conflict policy, so the first file's is everyone's
+    let mut plan = Plan::new(intent.on_conflict, left[0].action);
 
-    let new_path = new_dir.join(file.file_name());
+    let mut journal = Journal::open(&journal_path).context(Write, &journal_path)?;
+    let mut collector = Collector::new(observer);
+    collector.event(Event::Journal(journal.path()));
+    for operation in left.into_iter().filter_map(Record::operation) {
+        if action::is_done(operation.action, &operation.source, &operation.destination) {
+            let recorded = journal.record(operation.action, &operation.source, &operation.destination);
+            recorded.context(Write, journal.path())?;
+            collector.event(Event::Moved { operation: &operation, destination: &operation.destination });
+            continue;
+        }
+        if let Some(parent) = operation.destination.parent() {
+            plan.add_directory(parent.to_path_buf());
+        }
+        plan.add_operation(operation);
+    }
+    execute(&plan, &mut journal, 1, true, &mut collector)?;
+    Ok(collector.report)
+}
 
-    fs::create_dir_all(&new_dir)?;
-    fs::rename(&original_path, &new_path)?;
+// The journal of a run that is still going is left to that run.
+fn not_in_use(journal_path: &Path) -> Result<(), DorgError> {
+    match journal::in_use(journal_path).context(Read, journal_path)? {
+        true => Err(DorgError::Journal(format!("{:?} belongs to a run that is still going", journal_path))),
+        false => Ok(()),
+    }
+}
 
-    println!("File moved to {:?}", new_path);
-    Ok(())
+// The journals in `destination` of runs that were interrupted, oldest first, with how many
+// files each of them has left.
+pub fn interrupted_runs(destination: &Path) -> Result<Vec<(PathBuf, usize)>, DorgError> {
+    journal::interrupted(destination).context(ReadDir, destination)
 }
 
-fn get_creation_time(metadata: Metadata) -> Result<SystemTime, MetadataError> {
-    metadata.created().map_err(|e| {
-        if e.kind() == io::ErrorKind::Other {
-            MetadataError::CreationTimeUnavailable
-        } else {
-            MetadataError::IoError(e)
+// Removes `dir` and its ancestors while they are empty, stopping at `root`.
+fn remove_empty_dirs(dir: &Path, root: &Path) {
+    let mut current = Some(dir);
+    while let Some(dir) = current {
+        if dir == root || !dir.starts_with(root) || fs::remove_dir(dir).is_err() {
+            break;
         }
-    })
+        current = dir.parent();
+    }
 }
 
-fn get_modification_time(metadata: Metadata) -> Result<SystemTime, MetadataError> {
-    metadata.modified().map_err(|e| {
-        if e.kind() == io::ErrorKind::Other {
-            MetadataError::CreationTimeUnavailable
-        } else {
-            MetadataError::IoError(e)
+// Reading each file's metadata and dates is spread over `config.jobs` threads, while
+// conflicts are resolved one file at a time in walk order so that the plan is always the same.
+fn process_directory(config: &Config, plan: &mut Plan) -> Result<(), DorgError> {
+    let mut candidates = Vec::new();
+    for candidate in Walker::new(&config.directory_path, config.walk_depth())? {
+        match candidate {
+            Ok(candidate) => candidates.push(candidate),
+            // The walk carries on past a directory that cannot be read
+            Err(error) if config.keep_going => {
+                let path = error.path().unwrap_or(&config.directory_path).to_path_buf();
+                plan.fail(path, error);
+            }
+            Err(error) => return Err(error),
         }
-    })
+    }
+    parallel::for_each_ordered(
+        &candidates,
+        config.jobs,
+        |candidate| match skip_reason(config, &candidate.relative_path) {
+            _ if interrupt::requested() => Err(DorgError::Interrupted),
+            Some(reason) => Ok(Proposal::Skip(candidate.path.clone(), reason)),
+            None => propose(candidate, config),
+        },
+        |candidate, proposal| match proposal.and_then(|proposal| add_proposal(proposal, plan)) {
+            Err(error) if config.keep_going && !matches!(error, DorgError::Interrupted) => {
+                plan.fail(candidate.path.clone(), error);
+                Ok(())
+            }
+            result => result,
+        },
+    )
 }
 
-fn get_year_month_day(system_time: SystemTime) -> (i32, u32, u32) {
-    let datetime: DateTime<Utc> = system_time.into();
+fn skip_reason(config: &Config, relative_path: &Path) -> Option<String> {
+    if relative_path == Path::new(settings::FILE_NAME) {
+        return Some("configuration file".to_string());
+    }
+    config.filter.skip_reason(relative_path)
+}
 
-    let year = datetime.year();
-    let month = datetime.month();
-    let day = datetime.day();
+// Where a file would go, before checking it against the files already planned.
+enum Proposal {
+    Skip(PathBuf, String),
+    Place { source: PathBuf, destination: PathBuf, date: FileDate, date_source: SortType, action: Action },
+}
 
-    (year, month, day)
+// Where a file ended up, or why it was left alone after all.
+enum Placement {
+    Placed(PathBuf),
+    Skipped(String),
 }
 
-fn get_parent_dir(path: &Path) -> Option<PathBuf> {
-    if path.is_file() {
-        return Some(std::env::current_dir().ok()?);
+fn plan_file(file: &Candidate, config: &Config, plan: &mut Plan) -> Result<(), DorgError> {
+    add_proposal(propose(file, config)?, plan)
+}
+
+fn add_proposal(proposal: Proposal, plan: &mut Plan) -> Result<(), DorgError> {
+    let (source, new_path, date, date_source, action) = match proposal {
+        Proposal::Skip(path, reason) => {
+            plan.skip(path, reason);
+            return Ok(());
+        }
+        Proposal::Place { source, destination, date, date_source, action } => {
+            (source, destination, date, date_source, action)
+        }
+    };
+    let Some(new_dir) = new_path.parent().map(Path::to_path_buf) else {
+        return Err(DorgError::Destination { path: source, reason: "destination has no parent directory".into() });
+    };
+
+    match conflict::resolve(plan.on_conflict, &source, new_path, |path| plan.occupant(path)) {
+        Resolution::Move { destination, overwrite } => {
+            plan.add_directory(new_dir);
+            plan.add_operation(Operation { source, destination, overwrite, date, date_source, action });
+        }
+        Resolution::Skip(reason) => plan.skip(source, reason),
+        Resolution::Fail(reason) => return Err(DorgError::Conflict { path: source, reason }),
     }
-    for component in path.components() {
-        if let Component::Normal(root_dir) = component {
-            return Some(PathBuf::from(root_dir));
+    Ok(())
+}
+
+fn propose(file: &Candidate, config: &Config) -> Result<Proposal, DorgError> {
+    let original_path = file.path.clone();
+    let destination_root = &config.destination;
+
+    let metadata = fs::symlink_metadata(&original_path).context(Stat, &original_path)?;
+    let subject = Subject::new(&original_path, &file.relative_path, &metadata);
+    let rule = rule::first_match(&config.rules, &subject).context(Read, &original_path)?;
+    let action = match rule.and_then(|rule| rule.action) {
+        Some(RuleAction::Skip) => {
+            let name = rule.map(|rule| rule.name.as_str()).unwrap_or_default();
+            return Ok(Proposal::Skip(original_path, format!("skipped by rule `{name}`")));
+        }
+        Some(RuleAction::Place(action)) => action,
+        None => config.action,
+    };
+    let mode = rule.and_then(|rule| rule.mode.as_ref()).unwrap_or(&config.mode);
+    let date_sources = rule.and_then(|rule| rule.date_sources.as_deref()).unwrap_or(&config.date_sources);
+
+    let (file_date, date_source) = get_file_date(&original_path, &metadata, date_sources, config)
+        .map_err(|source| DorgError::Metadata { path: original_path.clone(), source })?;
+    let datetime = file_date.in_zone(config.timezone);
+    let format = if config.sniff { sniff::format(&original_path).context(Read, &original_path)? } else { None };
+    let no_file_name = || DorgError::Destination { path: original_path.clone(), reason: "it has no file name".into() };
+    let mut relative_path = file.relative_path.clone();
+    if let (Some(format), true) = (format, config.fix_extensions) {
+        let file_name = relative_path.file_name().ok_or_else(no_file_name)?;
+        if let Some(fixed) = sniff::fixed_name(file_name, format) {
+            relative_path.set_file_name(fixed);
         }
     }
-    Some(path.to_path_buf())
+    let file_name = relative_path.file_name().ok_or_else(no_file_name)?;
+    let category = match format {
+        Some(format) => config.categories.of_format(&relative_path, format),
+        None => config.categories.of(&relative_path),
+    };
+
+    let new_path = match mode {
+        Mode::Month => destination_root.join(Bucket::Month.directories(datetime)).join(file_name),
+        Mode::Day => destination_root.join(Bucket::Day.directories(datetime)).join(file_name),
+        Mode::Type(bucket) => {
+            let type_dir = destination_root.join(category);
+            match bucket {
+                Some(bucket) => type_dir.join(bucket.directories(datetime)).join(file_name),
+                None => type_dir.join(file_name),
+            }
+        }
+        Mode::Template(template) => {
+            let context = TemplateContext { datetime, relative_path: &relative_path, category };
+            let relative = template.render(&context).map_err(|reason| DorgError::Destination {
+                path: original_path.clone(),
+                reason: reason.into(),
+            })?;
+            destination_root.join(relative)
+        }
+    };
+
+    if new_path == original_path {
+        return Ok(Proposal::Skip(original_path, "already in place".to_string()));
+    }
+    Ok(Proposal::Place { source: original_path, destination: new_path, date: file_date, date_source, action })
+}
+
+fn place_file(
+    operation: &Operation,
+    on_conflict: ConflictPolicy,
+    directories: &DirectoryCache,
+) -> Result<Placement, DorgError> {
+    let action = operation.action;
+    let directory = operation.destination.parent().unwrap_or(Path::new("."));
+    directories.create(directory).context(Mkdir, directory)?;
+
+    let mut destination = operation.destination.clone();
+    let mut overwrite = operation.overwrite;
+    loop {
+        match action::perform(action, &operation.source, &destination, overwrite) {
+            Ok(()) => break,
+            // Something else created the destination after the plan was made
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                match conflict::resolve(on_conflict, &operation.source, destination, |path| {
+                    path.symlink_metadata().is_ok().then_some(Occupant::Existing)
+                }) {
+                    Resolution::Move { destination: next, overwrite: next_overwrite } => {
+                        destination = next;
+                        overwrite = next_overwrite;
+                    }
+                    Resolution::Skip(reason) => return Ok(Placement::Skipped(reason)),
+                    Resolution::Fail(reason) => {
+                        return Err(DorgError::Conflict { path: operation.source.clone(), reason });
+                    }
+                }
+            }
+            Err(e) => {
+                let operation_name = match action {
+                    Action::Move => Rename,
+                    Action::Copy => Copy,
+                    Action::Hardlink | Action::Symlink => Link,
+                };
+                return Err(e).context(operation_name, &operation.source);
+            }
+        }
+    }
+
+    Ok(Placement::Placed(destination))
+}
+
+// Returns the date from the first of `sources` that has one, and which source it was.
+fn get_file_date(
+    path: &Path,
+    metadata: &Metadata,
+    sources: &[SortType],
+    config: &Config,
+) -> Result<(FileDate, SortType), MetadataError> {
+    let mut last_error = MetadataError::CreationTimeUnavailable;
+    for source in sources {
+        let date = match source {
+            SortType::Created => get_creation_time(metadata).map(|time| FileDate::Instant(time.into())),
+            SortType::Modified => get_modification_time(metadata).map(|time| FileDate::Instant(time.into())),
+            SortType::Exif => exif::date_taken(path)?.ok_or(MetadataError::DateUnavailable(SortType::Exif)),
+            SortType::Video => video::creation_date(path)?.ok_or(MetadataError::DateUnavailable(SortType::Video)),
+            SortType::Filename => config
+                .filename_patterns
+                .date(path)
+                .ok_or(MetadataError::DateUnavailable(SortType::Filename)),
+        };
+        match date {
+            Ok(date) => return Ok((date, *source)),
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn get_creation_time(metadata: &Metadata) -> Result<SystemTime, MetadataError> {
+    metadata.created().map_err(|e| match error::is_time_unavailable(&e) {
+        true => MetadataError::CreationTimeUnavailable,
+        false => MetadataError::IoError(e),
+    })
+}
+
+fn get_modification_time(metadata: &Metadata) -> Result<SystemTime, MetadataError> {
+    metadata.modified().map_err(|e| match error::is_time_unavailable(&e) {
+        true => MetadataError::CreationTimeUnavailable,
+        false => MetadataError::IoError(e),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::{env, fs::{self, File}};
+    use std::fs::{self, File};
+    use chrono::TimeZone;
     use tempdir::TempDir;
 
+    struct Quiet;
+
+    impl Observer for Quiet {
+        fn event(&mut self, _: Event<'_>) {}
+    }
+
+    fn config(args: &[&str]) -> Config {
+        let args = ["dorg"].iter().chain(args).map(|arg| arg.to_string());
+        Config::build(args).expect("Failed to build config")
+    }
+
     #[test]
     fn test_move_file_month_created() {
         let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
@@ -197,25 +1012,725 @@ mod tests {
         let file_path = temp_dir_path.join("test_file.txt");
         File::create(&file_path).expect("Failed to create test file");
 
-        // Get the DirEntry for the dummy file
-        let dir_entry = fs::read_dir(&temp_dir_path)
+        // Get the Candidate for the dummy file
+        let candidate = Walker::new(temp_dir_path, 0)
             .expect("Failed to read temp dir")
             .next()
             .expect("No file found in temp dir")
-            .expect("Failed to get DirEntry");
+            .expect("Failed to get Candidate");
+
+        // Plan and move the file using the Mode::Month and SortType::Created
+        let config = config(&[temp_dir_path.to_str().unwrap(), "-mode=month", "-sort=created", "--tz=utc"]);
+        let mut plan = Plan::new(ConflictPolicy::Rename, Action::Move);
+        plan_file(&candidate, &config, &mut plan).expect("Failed to plan file");
+        let mut journal = Journal::create(temp_dir_path).expect("Failed to create journal");
+        execute(&plan, &mut journal, 1, false, &mut Quiet).expect("Failed to move file");
+
+        // Without `--dest`, the file is sorted inside the directory it is in
+        let month_dir = temp_dir_path.join(Utc::now().year().to_string()).join(Utc::now().month().to_string());
+        assert!(month_dir.join("test_file.txt").exists());
+    }
+
+    #[test]
+    fn test_plan_does_not_touch_disk() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let file_path = temp_dir.path().join("planned.txt");
+        File::create(&file_path).expect("Failed to create test file");
+
+        let config = config(&[temp_dir.path().to_str().unwrap(), "-mode=day", "-sort=modified", "--dry-run"]);
+        let plan = plan(&config).expect("Failed to build plan");
+
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].source, file_path);
+        assert!(file_path.exists());
+        assert!(!plan.operations[0].destination.exists());
+        for directory in &plan.directories {
+            assert!(!directory.exists());
+        }
+    }
+
+    #[test]
+    fn test_undo_restores_files_and_removes_empty_dirs() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let source = root.join("screenshots").join("shot.png");
+        let destination = root.join("2024").join("5").join("shot.png");
+
+        fs::create_dir_all(destination.parent().unwrap()).unwrap();
+        File::create(&destination).expect("Failed to create test file");
+
+        let mut journal = Journal::create(root).expect("Failed to create journal");
+        journal.record(Action::Move, &source, &destination).expect("Failed to write record");
+        let journal_path = journal.path().to_path_buf();
+        drop(journal);
+
+        struct Restored(Vec<PathBuf>);
+        impl Observer for Restored {
+            fn event(&mut self, event: Event<'_>) {
+                if let Event::Undone { source, .. } = event {
+                    self.0.push(source.to_path_buf());
+                }
+            }
+        }
+        let mut restored = Restored(Vec::new());
+        undo(Some(root), &mut restored).expect("Failed to undo");
+
+        assert_eq!(restored.0, vec![source.clone()]);
+        assert!(source.exists());
+        assert!(!destination.exists());
+        assert!(!root.join("2024").exists());
+        assert!(!journal_path.exists());
+    }
+
+    #[test]
+    fn test_explicit_destination_and_in_place() {
+        let source_dir = TempDir::new("test_source").expect("Failed to create temp dir");
+        let dest_dir = TempDir::new("test_dest").expect("Failed to create temp dir");
+        File::create(source_dir.path().join("photo.jpg")).expect("Failed to create test file");
+        let source = source_dir.path().to_str().unwrap();
+
+        let dest_arg = format!("--dest={}", dest_dir.path().display());
+        let to_dest = plan(&config(&[source, "-sort=modified", &dest_arg])).expect("Failed to build plan");
+        assert!(to_dest.operations[0].destination.is_absolute());
+        assert!(to_dest.operations[0].destination.starts_with(dest_dir.path()));
+
+        let in_place = plan(&config(&[source, "-sort=modified", "--in-place"])).expect("Failed to build plan");
+        assert!(in_place.operations[0].destination.starts_with(source_dir.path()));
+
+        // Not the current directory, wherever that is
+        assert_eq!(config(&[source]).destination, source_dir.path());
+        assert!(Config::build(["dorg", source, "--in-place", &dest_arg].map(String::from).into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_keep_newer_between_files_of_the_same_run() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        // Walked in this order, newest first
+        for (dir, day) in [("a", 20), ("b", 10)] {
+            fs::create_dir(root.join(dir)).unwrap();
+            let file = File::create(root.join(dir).join("IMG_0001.jpg")).expect("Failed to create test file");
+            file.set_modified(Utc.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap().into()).unwrap();
+        }
+
+        let dest = root.join("out");
+        let dest_arg = format!("--dest={}", dest.display());
+        let args = [root.to_str().unwrap(), "-r", "-sort=modified", "--tz=utc", "--on-conflict=keep-newer", &dest_arg];
+        let plan = plan(&config(&args)).expect("Failed to build plan");
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].source, root.join("a/IMG_0001.jpg"));
+        assert_eq!(plan.skipped.len(), 1);
+        assert_eq!(plan.skipped[0].path, root.join("b/IMG_0001.jpg"));
+
+        // The other way around, the later file takes the earlier one's place
+        for (dir, day) in [("a", 10), ("b", 20)] {
+            let file = File::options().write(true).open(root.join(dir).join("IMG_0001.jpg")).unwrap();
+            file.set_modified(Utc.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap().into()).unwrap();
+        }
+        let plan = super::plan(&config(&args)).expect("Failed to build plan");
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].source, root.join("b/IMG_0001.jpg"));
+        assert_eq!(plan.skipped[0].path, root.join("a/IMG_0001.jpg"));
+
+        let mut journal = Journal::create(&dest).unwrap();
+        execute(&plan, &mut journal, 2, false, &mut Quiet).expect("Failed to move files");
+        let placed = fs::metadata(dest.join("2024/5/IMG_0001.jpg")).unwrap().modified().unwrap();
+        assert_eq!(placed, Utc.with_ymd_and_hms(2024, 5, 20, 12, 0, 0).unwrap().into());
+        assert!(root.join("a/IMG_0001.jpg").exists());
+    }
+
+    #[test]
+    fn test_timezone_decides_the_day_bucket() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let file_path = temp_dir.path().join("screenshot.png");
+        let file = File::create(&file_path).expect("Failed to create test file");
+
+        // 9pm on May 31st in Mexico City, already June 1st in UTC
+        let modified = Utc.with_ymd_and_hms(2024, 6, 1, 3, 0, 0).unwrap();
+        file.set_modified(modified.into()).expect("Failed to set modification time");
+
+        let source = temp_dir.path().to_str().unwrap();
+        let dest = temp_dir.path().join("out");
+        let dest_arg = format!("--dest={}", dest.display());
+        let bucket = |tz: &str| {
+            let config = config(&[source, "-mode=day", "-sort=modified", &dest_arg, tz]);
+            plan(&config).expect("Failed to build plan").operations.remove(0).destination
+        };
+
+        assert_eq!(bucket("--tz=utc"), dest.join("2024/6/1/screenshot.png"));
+        assert_eq!(bucket("--tz=America/Mexico_City"), dest.join("2024/5/31/screenshot.png"));
+    }
+
+    #[test]
+    fn test_plan_records_date_source() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        File::create(temp_dir.path().join("notes.txt")).expect("Failed to create test file");
+        let source = temp_dir.path().to_str().unwrap();
+
+        let chained = config(&[source, "--date-source=modified,created", "--dry-run"]);
+        assert_eq!(chained.date_sources, vec![SortType::Modified, SortType::Created]);
+        let chained_plan = plan(&chained).expect("Failed to build plan");
+        assert_eq!(chained_plan.operations[0].date_source, SortType::Modified);
+
+        let exif = config(&[source, "-sort=exif"]);
+        assert_eq!(exif.date_sources, vec![SortType::Exif, SortType::Created, SortType::Modified]);
+        assert_ne!(plan(&exif).expect("Failed to build plan").operations[0].date_source, SortType::Exif);
+
+        let args = ["dorg", source, "--date-source=created,bogus"].map(String::from);
+        assert!(Config::build(args.into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_type_mode_with_and_without_dates() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let file = File::create(root.join("report.PDF")).expect("Failed to create test file");
+        file.set_modified(Utc.with_ymd_and_hms(2024, 5, 12, 12, 0, 0).unwrap().into()).unwrap();
+        File::create(root.join("photo.nef")).expect("Failed to create test file");
+        File::create(root.join("notes")).expect("Failed to create test file");
+
+        let source = root.to_str().unwrap();
+        let dest = root.join("out");
+        let dest_arg = format!("--dest={}", dest.display());
+        let destinations = |extra: &[&str]| -> Vec<PathBuf> {
+            let mut args = vec![source, "-sort=modified", "--tz=utc", &dest_arg];
+            args.extend(extra);
+            let plan = plan(&config(&args)).expect("Failed to build plan");
+            plan.operations.into_iter().map(|operation| operation.destination).collect()
+        };
+
+        assert_eq!(
+            destinations(&["-mode=type"]),
+            vec![dest.join("Other/notes"), dest.join("Images/photo.nef"), dest.join("Documents/report.PDF")]
+        );
+        let by_month = destinations(&["-mode=type/month", "--type-group=Raw=nef"]);
+        assert!(by_month[1].starts_with(dest.join("Raw")));
+        assert_eq!(by_month[2], dest.join("Documents/2024/5/report.PDF"));
+        assert_eq!(
+            destinations(&["--template={type}/{year}/{name}"])[2],
+            dest.join("Documents/2024/report.PDF")
+        );
+        assert!(Config::build(["dorg", source, "-mode=type/week"].map(String::from).into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_sniffed_type_and_fixed_extension() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::write(root.join("export.bin"), b"%PDF-1.7\n").expect("Failed to create test file");
+        fs::write(root.join("IMG_1"), b"\xFF\xD8\xFF\xE0\0\x10JFIF").expect("Failed to create test file");
+
+        let source = root.to_str().unwrap();
+        let dest = root.join("out");
+        let dest_arg = format!("--dest={}", dest.display());
+        let destinations = |extra: &str| -> Vec<PathBuf> {
+            let plan = plan(&config(&[source, "-sort=modified", "-mode=type", &dest_arg, extra]))
+                .expect("Failed to build plan");
+            plan.operations.into_iter().map(|operation| operation.destination).collect()
+        };
+
+        assert_eq!(destinations("--sniff"), vec![dest.join("Images/IMG_1"), dest.join("Documents/export.bin")]);
+        assert_eq!(
+            destinations("--fix-extensions"),
+            vec![dest.join("Images/IMG_1.jpg"), dest.join("Documents/export.pdf")]
+        );
+    }
+
+    #[test]
+    fn test_copy_leaves_source_and_undo_removes_copy() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::create_dir(root.join("card")).unwrap();
+        let source = root.join("card").join("IMG_1.jpg");
+        fs::write(&source, "photo").expect("Failed to create test file");
+
+        let dest_arg = format!("--dest={}", root.display());
+        let config = config(&[root.join("card").to_str().unwrap(), "-sort=modified", "--action=copy", &dest_arg]);
+        let plan = plan(&config).expect("Failed to build plan");
+        assert_eq!(plan.action, Action::Copy);
+        let mut journal = Journal::create(root).expect("Failed to create journal");
+        execute(&plan, &mut journal, 1, false, &mut Quiet).expect("Failed to copy file");
+        drop(journal);
+
+        let copy = &plan.operations[0].destination;
+        assert_eq!(fs::read_to_string(copy).unwrap(), "photo");
+        assert!(source.exists());
+
+        undo(Some(root), &mut Quiet).expect("Failed to undo");
+        assert!(!copy.exists());
+        assert!(source.exists());
+    }
+
+    #[test]
+    fn test_configuration_file_and_profiles() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::write(
+            root.join("dorg.toml"),
+            "exclude = ['*.tmp']\non-conflict = 'skip'\n\n[profile.shots]\nmode = 'day'\nin-place = true\n",
+        )
+        .unwrap();
+        let source = root.to_str().unwrap();
+
+        let defaults = config(&[source]);
+        assert!(matches!(defaults.mode, Mode::Month));
+        assert_eq!(defaults.on_conflict, ConflictPolicy::Skip);
+        assert_eq!(defaults.filter.exclude.len(), 1);
+
+        let profile = config(&[source, "--profile=shots"]);
+        assert!(matches!(profile.mode, Mode::Day));
+        assert_eq!(profile.destination, root);
+
+        let dest_arg = format!("--dest={}", root.join("out").display());
+        let overridden = config(&[source, "--profile=shots", "-mode=month", "--on-conflict=fail", &dest_arg]);
+        assert!(matches!(overridden.mode, Mode::Month));
+        assert_eq!(overridden.on_conflict, ConflictPolicy::Fail);
+        assert_eq!(overridden.destination, root.join("out"));
+
+        let build = |args: &[&str]| Config::build(["dorg"].iter().chain(args).map(|arg| arg.to_string()));
+        assert!(build(&[source, "--profile=videos"]).is_err());
+        let other_config = root.join("other.toml");
+        fs::write(&other_config, "mode = 'day'\nsort_by = 'exif'\n").unwrap();
+        let error = build(&[source, &format!("--config={}", other_config.display())]).err().unwrap();
+        assert!(error.to_string().ends_with("line 2: unknown key `sort_by`"), "{error}");
+
+        // Values are checked where the options are read, but still point back into the file
+        fs::write(&other_config, "mode = 'day'\njobs = 0\non-conflict = 'bogus'\n").unwrap();
+        let error = build(&[source, &format!("--config={}", other_config.display())]).err().unwrap();
+        let expected = format!("Invalid configuration {:?}, line 2: Invalid number of jobs", other_config);
+        assert_eq!(error.to_string(), expected);
+        let error = build(&[source, &format!("--config={}", other_config.display()), "-j2"]).err().unwrap();
+        assert!(error.to_string().ends_with("line 3: Invalid conflict policy"), "{error}");
+        let error = build(&[source, &format!("--config={}", other_config.display()), "-j2", "--on-conflict=x"]);
+        assert_eq!(error.err().unwrap().to_string(), "Invalid conflict policy");
+        fs::write(&other_config, "\nexclude = ['[a-']\n").unwrap();
+        let error = build(&[source, &format!("--config={}", other_config.display())]).err().unwrap();
+        assert!(error.to_string().contains("line 2: "), "{error}");
+    }
+
+    #[test]
+    fn test_first_matching_rule_decides() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let rules = r#"
+[[rule]]
+name = "screenshots"
+glob = "Screenshot*.png"
+mode = "day"
+date-source = "filename"
+
+[[rule]]
+extension = "pdf"
+mode = "type"
+action = "copy"
+
+[[rule]]
+name = "partial downloads"
+extension = ["part", "crdownload"]
+action = "skip"
+"#;
+        fs::write(root.join("dorg.toml"), rules).unwrap();
+        for name in ["Screenshot 2024-05-12 093011.png", "report.pdf", "video.mp4.part", "notes.txt"] {
+            File::create(root.join(name)).expect("Failed to create test file");
+        }
+
+        let dest = root.join("out");
+        let dest_arg = format!("--dest={}", dest.display());
+        let config = config(&[root.to_str().unwrap(), "-sort=modified", "--tz=utc", &dest_arg]);
+        let plan = plan(&config).expect("Failed to build plan");
+
+        let operation = |name: &str| {
+            let operation = plan.operations.iter().find(|operation| operation.source == root.join(name));
+            operation.expect("File was not planned")
+        };
+        let screenshot = operation("Screenshot 2024-05-12 093011.png");
+        assert_eq!(screenshot.destination, dest.join("2024/5/12/Screenshot 2024-05-12 093011.png"));
+        assert_eq!(screenshot.date_source, SortType::Filename);
+        assert_eq!(operation("report.pdf").destination, dest.join("Documents/report.pdf"));
+        assert_eq!(operation("report.pdf").action, Action::Copy);
+        assert_eq!(operation("notes.txt").action, Action::Move);
+        assert!(operation("notes.txt").destination.starts_with(dest.join(Utc::now().year().to_string())));
+        assert_eq!(plan.skipped[0].reason, "configuration file");
+        assert_eq!(plan.skipped[1].path, root.join("video.mp4.part"));
+        assert_eq!(plan.skipped[1].reason, "skipped by rule `partial downloads`");
+
+        let args = ["dorg", "explain", root.join("report.pdf").to_str().unwrap()].map(String::from);
+        assert!(matches!(Command::build(args.into_iter()), Ok(Command::Explain(..))));
+        let explanation = explain(&root.join("report.pdf"), &config).expect("Failed to explain").to_string();
+        assert!(explanation.starts_with("Rule `screenshots` does not match\n"), "{explanation}");
+        assert!(!explanation.contains("No rule matches"));
+        assert!(explanation.contains(&format!("-> {}", dest.join("Documents/report.pdf").display())));
+        let explanation = explain(&root.join("dorg.toml"), &config).unwrap().to_string();
+        assert!(explanation.ends_with("is not organized: configuration file\n"), "{explanation}");
+    }
+
+    #[test]
+    fn test_filtered_files_are_skipped_with_reason() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("partial")).unwrap();
+        File::create(root.join("IMG_1.JPG")).expect("Failed to create test file");
+        File::create(root.join("desktop.ini")).expect("Failed to create test file");
+        File::create(root.join("partial").join("IMG_2.jpg")).expect("Failed to create test file");
+
+        let config = config(&[
+            root.to_str().unwrap(),
+            "-r",
+            "-sort=modified",
+            "--include=*.jpg",
+            "--exclude=partial/**",
+            "--ignore-case",
+        ]);
+        let plan = plan(&config).expect("Failed to build plan");
+
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].source, root.join("IMG_1.JPG"));
+        let reason_for = |name: &str| {
+            let skipped = plan.skipped.iter().find(|skipped| skipped.path == root.join(name));
+            skipped.map(|skipped| skipped.reason.as_str())
+        };
+        assert_eq!(reason_for("desktop.ini"), Some("not matched by any include pattern"));
+        assert_eq!(reason_for("partial/IMG_2.jpg"), Some("excluded by `partial/**`"));
+    }
+
+    #[test]
+    fn test_watched_files_share_one_journal() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let args = ["dorg", "watch", root.to_str().unwrap(), "--poll", "--settle=0.5", "-sort=modified", "--in-place", "-r"];
+        let Ok(Command::Watch(config, options)) = Command::build(args.map(String::from).into_iter()) else {
+            panic!("Failed to parse watch command");
+        };
+        assert!(options.poll);
+        assert_eq!(options.settle, Duration::from_millis(500));
+
+        let mut journal = None;
+        let mut placed = HashSet::new();
+        for name in ["a.txt", "b.txt"] {
+            File::create(root.join(name)).expect("Failed to create test file");
+            let path = root.join(name);
+            organize_new_file(&path, &config, &mut journal, &mut placed, &mut Quiet).expect("Failed to organize");
+        }
+
+        let records = journal::read(journal.expect("No journal was written").path()).unwrap();
+        assert_eq!(records.iter().filter(|record| matches!(record.state, State::Placed)).count(), 2);
+        assert!(journal::pending(&records).is_empty());
+        assert_eq!(placed.len(), 2);
+        assert!(placed.iter().all(|destination| destination.exists()));
+        assert!(!root.join("a.txt").exists());
+
+        // Nothing to remember when the files leave the watched directory
+        let outside = TempDir::new("test_dest").expect("Failed to create temp dir");
+        let dest_arg = format!("--dest={}", outside.path().display());
+        let args = ["dorg", "watch", root.to_str().unwrap(), "--poll", "-sort=modified", "-r", &dest_arg];
+        let Ok(Command::Watch(config, _)) = Command::build(args.map(String::from).into_iter()) else {
+            panic!("Failed to parse watch command");
+        };
+        let path = root.join("c.txt");
+        File::create(&path).expect("Failed to create test file");
+        let mut journal = None;
+        organize_new_file(&path, &config, &mut journal, &mut placed, &mut Quiet).expect("Failed to organize");
+        assert!(!path.exists());
+        assert_eq!(placed.len(), 2);
+
+        // Nor when the watcher does not look into the directories they go to
+        let args = ["dorg", "watch", root.to_str().unwrap(), "--poll", "-sort=modified", "--in-place"];
+        let Ok(Command::Watch(config, _)) = Command::build(args.map(String::from).into_iter()) else {
+            panic!("Failed to parse watch command");
+        };
+        let path = root.join("d.txt");
+        File::create(&path).expect("Failed to create test file");
+        let mut journal = None;
+        organize_new_file(&path, &config, &mut journal, &mut placed, &mut Quiet).expect("Failed to organize");
+        assert!(!path.exists());
+        assert_eq!(placed.len(), 2);
+    }
+
+    #[test]
+    fn test_watch_dry_run_only_reports() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::write(root.join(settings::FILE_NAME), "dry-run = true\n").unwrap();
+        let args = ["dorg", "watch", root.to_str().unwrap(), "--poll", "-sort=modified", "--in-place"];
+        let Ok(Command::Watch(config, _)) = Command::build(args.map(String::from).into_iter()) else {
+            panic!("Failed to parse watch command");
+        };
+        assert!(config.dry_run);
+
+        struct Planned(usize);
+        impl Observer for Planned {
+            fn event(&mut self, event: Event<'_>) {
+                if let Event::Planned(_) = event {
+                    self.0 += 1;
+                }
+            }
+        }
+        let mut planned = Planned(0);
+        let mut journal = None;
+        let mut placed = HashSet::new();
+        let path = root.join("a.txt");
+        File::create(&path).expect("Failed to create test file");
+        organize_new_file(&path, &config, &mut journal, &mut placed, &mut planned).expect("Failed to organize");
 
-        // Move the file using the Mode::Month and SortType::Created
-        move_file(dir_entry, &Mode::Month, &SortType::Created).expect("Failed to move file");
+        assert_eq!(planned.0, 1);
+        assert!(path.exists());
+        assert!(journal.is_none());
+        assert!(placed.is_empty());
+    }
+
+    #[test]
+    fn test_parallel_plan_matches_sequential_plan() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for dir in 0..20 {
+            fs::create_dir_all(root.join(format!("dir{dir:02}"))).unwrap();
+            for name in ["IMG_1.jpg", &format!("IMG_{dir}_2.jpg")] {
+                File::create(root.join(format!("dir{dir:02}")).join(name)).expect("Failed to create test file");
+            }
+        }
+
+        let dest = root.join("out");
+        let dest_arg = format!("--dest={}", dest.display());
+        let args = [root.to_str().unwrap(), "-r", "-sort=modified", "--tz=utc", &dest_arg];
+        let destinations = |plan: &Plan| plan.operations.iter().map(|op| op.destination.clone()).collect::<Vec<_>>();
+        let sequential = plan(&config(&args)).expect("Failed to build plan");
+        let parallel = plan(&config(&[&args[..], &["-j", "8"]].concat())).expect("Failed to build plan");
+        assert_eq!(destinations(&parallel), destinations(&sequential));
+        assert!(parallel.operations.iter().any(|op| op.destination.ends_with("IMG_1 (20).jpg")));
+
+        let mut journal = Journal::create(&dest).unwrap();
+        execute(&parallel, &mut journal, 8, false, &mut Quiet).expect("Failed to move files");
+        let records = journal::read(journal.path()).unwrap();
+        let placed = records.iter().filter(|record| matches!(record.state, State::Placed));
+        let recorded = placed.map(|record| record.destination.clone()).collect::<Vec<_>>();
+        assert_eq!(recorded, destinations(&parallel));
+        assert!(recorded.iter().all(|destination| destination.exists()));
+
+        assert_eq!(config(&[root.to_str().unwrap(), "--jobs=3"]).jobs, 3);
+        assert!(Config::build(["dorg", root.to_str().unwrap(), "-j0"].map(String::from).into_iter()).is_err());
+    }
+
+    #[test]
+    fn test_errors_name_the_path_and_operation() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let source = root.join("gone");
+        fs::create_dir(&source).unwrap();
+        let config = config(&[source.to_str().unwrap()]);
+        fs::remove_dir(&source).unwrap();
+
+        let error = plan(&config).err().expect("Planning a missing directory should fail");
+        assert!(
+            matches!(&error, DorgError::Io { operation: IoOperation::ReadDir, path, .. } if *path == source),
+            "{error:?}"
+        );
+        assert!(error.to_string().starts_with(&format!("read_dir {:?} failed: ", source)), "{error}");
+
+        let error = Command::build(["dorg".to_string()].into_iter()).err().unwrap();
+        assert!(matches!(error, DorgError::Usage(_)));
+        assert!(matches!(undo(Some(root), &mut Quiet).err().unwrap(), DorgError::Journal(_)));
+    }
+
+    #[test]
+    fn test_template_cannot_leave_the_destination() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("src")).unwrap();
+        for name in ["...", "a.jpg"] {
+            File::create(root.join("src").join(name)).expect("Failed to create test file");
+        }
+        let source = root.join("src");
+        let dest = root.join("out");
+        let dest_arg = format!("--dest={}", dest.display());
+        let args = [source.to_str().unwrap(), "--template={stem}/{name}", "-sort=modified", &dest_arg];
+
+        let error = plan(&config(&args)).err().expect("`...` would be placed outside the destination");
+        assert!(matches!(error, DorgError::Destination { .. }));
+        let plan = plan(&config(&[&args[..], &["--keep-going"]].concat())).expect("Failed to build plan");
+        assert_eq!(plan.failed.len(), 1);
+        assert_eq!(plan.failed[0].path, root.join("src/..."));
+        assert_eq!(plan.operations.len(), 1);
+        assert_eq!(plan.operations[0].destination, dest.join("a/a.jpg"));
+    }
 
-        // Check if the file has been moved to the expected location
-        let current_dir = env::current_dir().unwrap();
-        let year_dir: PathBuf = current_dir.join(Utc::now().year().to_string());
-        let month_dir = year_dir.join(Utc::now().month().to_string());
-        let moved_file_path = current_dir.join(&year_dir).join(month_dir).join("test_file.txt");
+    #[test]
+    fn test_keep_going_collects_failures() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for name in ["IMG_20240512_093011.jpg", "IMG_20240513_093011.jpg", "notes.txt"] {
+            File::create(root.join(name)).expect("Failed to create test file");
+        }
+        let args = [root.to_str().unwrap(), "--in-place", "--date-source=filename", "--on-conflict=fail"];
+
+        let error = plan(&config(&args)).err().expect("notes.txt has no date");
+        assert_eq!(error.kind(), FailureKind::NoTimestamp);
+
+        let plan = plan(&config(&[&args[..], &["--keep-going"]].concat())).expect("Failed to build plan");
+        assert_eq!(plan.operations.len(), 2);
+        assert_eq!(plan.failed.len(), 1);
+        assert_eq!(plan.failed[0].path, root.join("notes.txt"));
+
+        // Taken after planning, so only found out while moving
+        fs::create_dir_all(root.join("2024/5")).unwrap();
+        File::create(root.join("2024/5/IMG_20240513_093011.jpg")).unwrap();
+        struct Failures(Vec<FailureKind>);
+        impl Observer for Failures {
+            fn event(&mut self, event: Event<'_>) {
+                if let Event::Failed { error, .. } = event {
+                    self.0.push(error.kind());
+                }
+            }
+        }
+        let mut failures = Failures(Vec::new());
+        let mut journal = Journal::create(root).unwrap();
+        execute(&plan, &mut journal, 1, true, &mut failures).expect("Failures should not stop the run");
+        assert_eq!(failures.0, [FailureKind::Conflict]);
+        assert!(root.join("2024/5/IMG_20240512_093011.jpg").exists());
+        assert!(root.join("IMG_20240513_093011.jpg").exists());
+    }
+
+    #[test]
+    fn test_files_placed_around_a_failure_can_be_undone() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let names: Vec<String> = (10..30).map(|day| format!("IMG_202405{day}_093011.jpg")).collect();
+        for name in &names {
+            File::create(root.join(name)).expect("Failed to create test file");
+        }
+        let args = [root.to_str().unwrap(), "--in-place", "--date-source=filename", "--on-conflict=fail"];
+        let plan = plan(&config(&args)).expect("Failed to build plan");
+
+        // Taken after planning, so the run stops in the middle
+        fs::create_dir_all(root.join("2024/5")).unwrap();
+        let blocker = root.join("2024/5").join(&names[8]);
+        File::create(&blocker).unwrap();
+        let mut journal = Journal::create(root).unwrap();
+        let error = execute(&plan, &mut journal, 4, false, &mut Quiet).expect_err("The run should stop");
+        assert_eq!(error.kind(), FailureKind::Conflict);
+        drop(journal);
+
+        fs::remove_file(&blocker).unwrap();
+        undo(Some(root), &mut Quiet).expect("Failed to undo");
+        assert!(names.iter().all(|name| root.join(name).exists()));
+        assert!(!root.join("2024").exists());
+    }
+
+    #[test]
+    fn test_stopped_run_leaves_no_empty_directories() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for name in ["IMG_20240512_093011.jpg", "IMG_20240612_093011.jpg"] {
+            File::create(root.join(name)).expect("Failed to create test file");
+        }
+        let args = [root.to_str().unwrap(), "--in-place", "--date-source=filename", "--on-conflict=fail"];
+        let plan = plan(&config(&args)).expect("Failed to build plan");
+        assert_eq!(plan.directories.len(), 2);
+
+        fs::create_dir_all(root.join("2024/5")).unwrap();
+        File::create(root.join("2024/5/IMG_20240512_093011.jpg")).unwrap();
+        let mut journal = Journal::create(root).unwrap();
+        execute(&plan, &mut journal, 1, false, &mut Quiet).expect_err("The run should stop");
+        assert!(!root.join("2024/6").exists());
+    }
+
+    #[test]
+    fn test_overwrite_keeps_the_last_file_in_plan_order() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let sources = root.join("sources");
+        for index in 0..16 {
+            let directory = sources.join(format!("{index:02}"));
+            fs::create_dir_all(&directory).unwrap();
+            fs::write(directory.join("IMG_20240512_093011.jpg"), format!("{index:02}")).unwrap();
+        }
+        let destination = root.join("out");
+        let args = [
+            sources.to_str().unwrap(), "-r", "--date-source=filename", "--on-conflict=overwrite",
+            &format!("--dest={}", destination.display()),
+        ];
+        let plan = plan(&config(&args)).expect("Failed to build plan");
+        assert_eq!(plan.operations.len(), 16);
+
+        let mut journal = Journal::create(root).unwrap();
+        execute(&plan, &mut journal, 8, false, &mut Quiet).expect("Failed to move files");
+        let last = plan.operations.last().unwrap();
+        let expected = last.source.parent().unwrap().file_name().unwrap().to_str().unwrap();
+        assert_eq!(fs::read_to_string(&last.destination).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_undo_reverses_files_an_interrupted_run_did_not_record() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for name in ["IMG_20240511_093011.jpg", "IMG_20240512_093011.jpg", "IMG_20240513_093011.jpg"] {
+            File::create(root.join(name)).expect("Failed to create test file");
+        }
+        let plan = plan(&config(&[root.to_str().unwrap(), "--in-place", "-sort=filename"])).unwrap();
+
+        // Killed after moving the second file, before recording it
+        let mut journal = Journal::create(root).unwrap();
+        journal.plan(&plan.operations, plan.on_conflict).unwrap();
+        fs::create_dir_all(root.join("2024/5")).unwrap();
+        for operation in &plan.operations[..2] {
+            fs::rename(&operation.source, &operation.destination).unwrap();
+        }
+        let first = &plan.operations[0];
+        journal.record(first.action, &first.source, &first.destination).unwrap();
+        drop(journal);
+
+        undo(Some(root), &mut Quiet).expect("Failed to undo");
+        assert!(plan.operations.iter().all(|operation| operation.source.exists()));
+        assert!(!root.join("2024").exists());
+    }
+
+    #[test]
+    fn test_resume_finishes_an_interrupted_run() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for name in ["IMG_20240511_093011.jpg", "IMG_20240512_093011.jpg", "IMG_20240513_093011.jpg"] {
+            File::create(root.join(name)).expect("Failed to create test file");
+        }
+        let plan = plan(&config(&[root.to_str().unwrap(), "--in-place", "-sort=filename"])).unwrap();
+
+        // Killed after moving the second file, before recording it
+        let mut journal = Journal::create(root).unwrap();
+        journal.plan(&plan.operations, plan.on_conflict).unwrap();
+        fs::create_dir_all(root.join("2024/5")).unwrap();
+        for operation in &plan.operations[..2] {
+            fs::rename(&operation.source, &operation.destination).unwrap();
+        }
+        let first = &plan.operations[0];
+        journal.record(first.action, &first.source, &first.destination).unwrap();
+        let journal_path = journal.path().to_path_buf();
+        drop(journal);
+        assert_eq!(interrupted_runs(root).unwrap(), [(journal_path, 2)]);
+
+        let report = resume(Some(root), &mut Quiet).expect("Failed to resume");
+        assert_eq!(report.placed.len(), 2);
+        assert!(report.failed.is_empty());
+        assert!(plan.operations.iter().all(|operation| operation.destination.exists()));
+        assert!(interrupted_runs(root).unwrap().is_empty());
+        assert!(matches!(resume(Some(root), &mut Quiet), Err(DorgError::Journal(_))));
+
+        undo(Some(root), &mut Quiet).expect("Failed to undo");
+        assert!(plan.operations.iter().all(|operation| operation.source.exists()));
+    }
+
+    #[test]
+    fn test_runs_that_are_still_going_are_left_alone() {
+        let temp_dir = TempDir::new("test_dir").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        File::create(root.join("IMG_20240511_093011.jpg")).expect("Failed to create test file");
+        let plan = plan(&config(&[root.to_str().unwrap(), "--in-place", "-sort=filename"])).unwrap();
 
-        assert!(moved_file_path.exists());
+        let mut journal = Journal::create(root).unwrap();
+        journal.plan(&plan.operations, plan.on_conflict).unwrap();
+        assert!(interrupted_runs(root).unwrap().is_empty());
+        assert!(matches!(resume(Some(journal.path()), &mut Quiet), Err(DorgError::Journal(_))));
+        assert!(matches!(undo(Some(root), &mut Quiet), Err(DorgError::Journal(_))));
+        assert!(Journal::open(journal.path()).is_err());
 
-        // Cleanup
-        fs::remove_dir_all(current_dir.join(&year_dir)).expect("Failed to remove temp dir");
+        drop(journal);
+        assert_eq!(interrupted_runs(root).unwrap().len(), 1);
     }
 }
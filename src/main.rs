@@ -1,16 +1,104 @@
 use std::env;
+use std::path::{Path, PathBuf};
 use std::process;
 
-use dorg::Config;
+use dorg::{Command, Config, DorgError, Organizer, Output, Printer, WatchOptions, Zone};
 
 fn main() {
-    let config = Config::build(env::args()).unwrap_or_else(|err| {
+    let command = Command::build(env::args()).unwrap_or_else(|err| {
         eprintln!("Error parsing arguments: {err}");
-        process::exit(1);
+        process::exit(exit_code(&err));
     });
-    
-    if let Err(e) = dorg::run(config) {
+
+    let result = match command {
+        Command::Organize(config) => organize(config),
+        Command::Undo(target) => undo(target),
+        Command::Resume(target) => resume(target),
+        Command::Explain(file, config) => dorg::explain(&file, &config).map(|explanation| print!("{explanation}")),
+        Command::Watch(config, options) => watch(config, options),
+    };
+
+    if let Err(e) = result {
         eprintln!("Application error: {e}");
-        process::exit(1);
+        process::exit(exit_code(&e));
+    }
+}
+
+// Each class of error exits with its own code, so scripts can tell them apart.
+fn exit_code(error: &DorgError) -> i32 {
+    match error {
+        DorgError::Incomplete { .. } => 1,
+        DorgError::Usage(_) => 2,
+        DorgError::Io { .. } => 3,
+        DorgError::Metadata { .. } => 4,
+        DorgError::Conflict { .. } => 5,
+        DorgError::Destination { .. } => 6,
+        DorgError::Journal(_) => 7,
+        // 128 + SIGINT
+        DorgError::Interrupted => 130,
+    }
+}
+
+// Only files that were kept going past are left to fail once a run is over.
+fn completed(failed: usize) -> Result<(), DorgError> {
+    match failed {
+        0 => Ok(()),
+        failed => Err(DorgError::Incomplete { failed }),
+    }
+}
+
+fn warn_about_interrupted_runs(destination: &Path) {
+    for (journal, left) in dorg::interrupted_runs(destination).unwrap_or_default() {
+        eprintln!("Warning: the run in {:?} was interrupted with {left} file(s) left, `dorg resume` finishes it", journal);
+    }
+}
+
+fn organize(config: Config) -> Result<(), DorgError> {
+    warn_about_interrupted_runs(&config.destination);
+    let mut printer = Printer::new(config.output, config.timezone);
+    let dry_run = config.dry_run;
+    if !dry_run {
+        dorg::handle_interrupts();
+    }
+    let organizer = Organizer::from_config(config);
+    let failed = if dry_run {
+        organizer.plan().map(|plan| {
+            printer.plan(&plan);
+            plan.failed.len()
+        })
+    } else {
+        organizer.run(&mut printer).map(|report| report.failed.len())
+    };
+    let result = failed.and_then(completed);
+    printer.finish(result.as_ref().err());
+    result
+}
+
+fn undo(target: Option<PathBuf>) -> Result<(), DorgError> {
+    let mut printer = Printer::new(Output::Text, Zone::Local);
+    let result = dorg::undo(target.as_deref(), &mut printer);
+    printer.finish(result.as_ref().err());
+    result
+}
+
+fn resume(target: Option<PathBuf>) -> Result<(), DorgError> {
+    dorg::handle_interrupts();
+    let mut printer = Printer::new(Output::Text, Zone::Local);
+    let result = dorg::resume(target.as_deref(), &mut printer).and_then(|report| completed(report.failed.len()));
+    printer.finish(result.as_ref().err());
+    result
+}
+
+fn watch(config: Config, options: WatchOptions) -> Result<(), DorgError> {
+    warn_about_interrupted_runs(&config.destination);
+    let mut printer = Printer::new(config.output, config.timezone).dry_run(config.dry_run);
+    let organizer = Organizer::from_config(config);
+    let watcher = organizer.watcher(&options)?;
+    if let Some(e) = watcher.inotify_error() {
+        eprintln!("inotify is unavailable ({e}), rescanning the directory instead");
+    }
+    if organizer.config().output == Output::Text {
+        println!("Watching {:?} for new files ({})", organizer.config().directory_path, watcher.backend());
     }
+    organizer.watch(watcher, &mut printer)
 }
@@ -0,0 +1,256 @@
+// The library entry point: an `Organizer` is set up with a builder, reports what it does to an
+// `Observer` as it goes and returns a `Report` of the whole run.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{DorgError, FailureKind, IoContext, IoOperation};
+use crate::interrupt;
+use crate::journal::Journal;
+use crate::plan::{Operation, Plan, Skipped};
+use crate::watch::{WatchOptions, Watcher};
+use crate::{Action, Config, ConflictPolicy, Filter, Mode, SortType};
+
+#[derive(Clone, Copy)]
+pub enum Event<'a> {
+    // A file will be placed at a destination. Sent for every file before any of them is.
+    Planned(&'a Operation),
+    // A file was placed at `destination` with the operation's action, which is not always a
+    // move. `destination` differs from the planned one if a conflict came up in the meantime.
+    Moved { operation: &'a Operation, destination: &'a Path },
+    Skipped { path: &'a Path, reason: &'a str },
+    Failed { path: &'a Path, error: &'a DorgError },
+    // The journal that the files placed from now on are recorded in was created.
+    Journal(&'a Path),
+    // A file placed at `destination` by an earlier run was moved back to `source`, or removed
+    // if `action` left the source where it was.
+    Undone { source: &'a Path, destination: &'a Path, action: Action },
+}
+
+pub trait Observer {
+    fn event(&mut self, event: Event<'_>);
+}
+
+// A file that was placed, and where.
+pub struct Placed {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub action: Action,
+    pub date_source: SortType,
+}
+
+// A file that could not be organized, with `keep_going`.
+pub struct Failed {
+    pub path: PathBuf,
+    pub kind: FailureKind,
+    pub error: String,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub placed: Vec<Placed>,
+    pub skipped: Vec<Skipped>,
+    pub failed: Vec<Failed>,
+    // Not written when there was nothing to do.
+    pub journal: Option<PathBuf>,
+}
+
+pub struct Organizer {
+    config: Config,
+}
+
+impl Organizer {
+    // Organizes `source` in place, non-recursively, by month, until told otherwise. The date is
+    // the creation time, or the modification time where the filesystem does not record one.
+    pub fn new(source: impl Into<PathBuf>) -> Organizer {
+        Organizer { config: Config::new(source.into()) }
+    }
+
+    pub fn from_config(config: Config) -> Organizer {
+        Organizer { config }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn destination(mut self, destination: impl Into<PathBuf>) -> Organizer {
+        self.config.destination = destination.into();
+        self
+    }
+
+    pub fn recursive(mut self, recursive: bool) -> Organizer {
+        self.config.recursive = recursive;
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Organizer {
+        self.config.mode = mode;
+        self
+    }
+
+    // Tried in order for each file until one of them yields a date.
+    pub fn date_sources(mut self, date_sources: Vec<SortType>) -> Organizer {
+        self.config.date_sources = date_sources;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Organizer {
+        self.config.filter = filter;
+        self
+    }
+
+    pub fn action(mut self, action: Action) -> Organizer {
+        self.config.action = action;
+        self
+    }
+
+    pub fn on_conflict(mut self, on_conflict: ConflictPolicy) -> Organizer {
+        self.config.on_conflict = on_conflict;
+        self
+    }
+
+    pub fn jobs(mut self, jobs: usize) -> Organizer {
+        self.config.jobs = jobs.max(1);
+        self
+    }
+
+    // Files that fail are reported and left where they are, and the run carries on with the
+    // others. Otherwise the first failure ends the run with an error.
+    pub fn keep_going(mut self, keep_going: bool) -> Organizer {
+        self.config.keep_going = keep_going;
+        self
+    }
+
+    // What would be done, without touching the disk.
+    pub fn plan(&self) -> Result<Plan, DorgError> {
+        crate::plan(&self.config)
+    }
+
+    pub fn run(&self, observer: &mut dyn Observer) -> Result<Report, DorgError> {
+        let plan = self.plan()?;
+        let mut collector = Collector::new(observer);
+        for skipped in &plan.skipped {
+            collector.event(Event::Skipped { path: &skipped.path, reason: &skipped.reason });
+        }
+        for failure in &plan.failed {
+            collector.event(Event::Failed { path: &failure.path, error: &failure.error });
+        }
+        for operation in &plan.operations {
+            collector.event(Event::Planned(operation));
+        }
+
+        if interrupt::requested() {
+            return Err(DorgError::Interrupted);
+        }
+        if !plan.is_empty() {
+            let destination = &self.config.destination;
+            let mut journal = Journal::create(destination).context(IoOperation::Write, destination)?;
+            collector.event(Event::Journal(journal.path()));
+            crate::execute(&plan, &mut journal, self.config.jobs, self.config.keep_going, &mut collector)?;
+        }
+        Ok(collector.report)
+    }
+
+    pub fn watcher(&self, options: &WatchOptions) -> Result<Watcher, DorgError> {
+        Watcher::new(&self.config.directory_path, self.config.walk_depth(), options)
+    }
+
+    // Organizes each file the watcher reports, until the process is stopped.
+    pub fn watch(&self, watcher: Watcher, observer: &mut dyn Observer) -> Result<(), DorgError> {
+        crate::watch(&self.config, watcher, observer)
+    }
+}
+
+// Builds the report from the events on their way to the caller's observer.
+pub(crate) struct Collector<'a> {
+    pub(crate) report: Report,
+    observer: &'a mut dyn Observer,
+}
+
+impl Collector<'_> {
+    pub(crate) fn new(observer: &mut dyn Observer) -> Collector<'_> {
+        Collector { report: Report::default(), observer }
+    }
+}
+
+impl Observer for Collector<'_> {
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            Event::Moved { operation, destination } => self.report.placed.push(Placed {
+                source: operation.source.clone(),
+                destination: destination.to_path_buf(),
+                action: operation.action,
+                date_source: operation.date_source,
+            }),
+            Event::Skipped { path, reason } => {
+                self.report.skipped.push(Skipped { path: path.to_path_buf(), reason: reason.to_string() })
+            }
+            Event::Failed { path, error } => self.report.failed.push(Failed {
+                path: path.to_path_buf(),
+                kind: error.kind(),
+                error: error.to_string(),
+            }),
+            Event::Journal(path) => self.report.journal = Some(path.to_path_buf()),
+            Event::Planned(_) | Event::Undone { .. } => {}
+        }
+        self.observer.event(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use tempdir::TempDir;
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Observer for Recorder {
+        fn event(&mut self, event: Event<'_>) {
+            let name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            self.0.push(match event {
+                Event::Planned(operation) => format!("planned {}", name(&operation.source)),
+                Event::Moved { operation, .. } => format!("moved {}", name(&operation.source)),
+                Event::Skipped { path, reason } => format!("skipped {}: {reason}", name(path)),
+                Event::Failed { path, .. } => format!("failed {}", name(path)),
+                Event::Journal(_) => "journal".to_string(),
+                Event::Undone { source, .. } => format!("undone {}", name(source)),
+            });
+        }
+    }
+
+    #[test]
+    fn test_organizer_reports_events_and_result() {
+        let temp_dir = TempDir::new("test_organizer").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        for name in ["a.txt", "b.jpg", "c.tmp"] {
+            File::create(root.join(name)).expect("Failed to create test file");
+        }
+
+        let filter = Filter { exclude: vec![crate::Glob::new("*.tmp", false).unwrap()], ..Filter::default() };
+        let organizer = Organizer::new(root)
+            .destination(root.join("out"))
+            .mode(Mode::Type(None))
+            .date_sources(vec![SortType::Modified])
+            .filter(filter)
+            .action(Action::Copy);
+        let mut recorder = Recorder::default();
+        let report = organizer.run(&mut recorder).expect("Failed to organize");
+
+        assert_eq!(recorder.0, [
+            "skipped c.tmp: excluded by `*.tmp`",
+            "planned a.txt",
+            "planned b.jpg",
+            "journal",
+            "moved a.txt",
+            "moved b.jpg",
+        ]);
+        assert_eq!(report.placed.len(), 2);
+        assert_eq!(report.placed[1].destination, root.join("out/Images/b.jpg"));
+        assert_eq!(report.placed[1].action, Action::Copy);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.journal.is_some_and(|journal| journal.exists()));
+        assert!(fs::exists(root.join("b.jpg")).unwrap());
+    }
+}
@@ -0,0 +1,367 @@
+// What the command line prints about each file: the usual lines of text, or one JSON record
+// per file followed by a summary record, for feeding into other programs.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::organizer::{Event, Failed, Observer};
+use crate::plan::Plan;
+use crate::{DorgError, FileDate, Zone};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Output {
+    Text,
+    // A single JSON array of records.
+    Json,
+    // One JSON record per line, written as soon as each file is done.
+    Ndjson,
+}
+
+impl Output {
+    pub fn parse(output: &str) -> Option<Output> {
+        match output {
+            "text" => Some(Output::Text),
+            "json" => Some(Output::Json),
+            "ndjson" => Some(Output::Ndjson),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counts {
+    planned: usize,
+    done: usize,
+    skipped: usize,
+    failed: usize,
+}
+
+pub struct Printer {
+    output: Output,
+    // Dates with a known instant are written with this zone's offset.
+    timezone: Zone,
+    counts: Counts,
+    failures: Vec<Failed>,
+    journal: Option<String>,
+    // Whether files are reported as planned rather than done, as `dorg watch --dry-run` does.
+    dry_run: bool,
+    // Whether the opening bracket of the JSON array has been written.
+    started: bool,
+}
+
+impl Printer {
+    pub fn new(output: Output, timezone: Zone) -> Printer {
+        Printer {
+            output,
+            timezone,
+            counts: Counts::default(),
+            failures: Vec::new(),
+            journal: None,
+            dry_run: false,
+            started: false,
+        }
+    }
+
+    // Reports each planned file as it is planned.
+    pub fn dry_run(mut self, dry_run: bool) -> Printer {
+        self.dry_run = dry_run;
+        self
+    }
+
+    // Shows a plan that is not going to be carried out.
+    pub fn plan(&mut self, plan: &Plan) {
+        if self.output == Output::Text {
+            print!("{plan}");
+            // Already listed in the plan, but still part of the summary
+            for failure in &plan.failed {
+                self.fail(&failure.path, &failure.error);
+            }
+            return;
+        }
+        for skipped in &plan.skipped {
+            self.event(Event::Skipped { path: &skipped.path, reason: &skipped.reason });
+        }
+        for failure in &plan.failed {
+            self.event(Event::Failed { path: &failure.path, error: &failure.error });
+        }
+        for operation in &plan.operations {
+            self.counts.planned += 1;
+            if let Some(record) = self.record(Event::Planned(operation)) {
+                self.write(&record);
+            }
+        }
+    }
+
+    // Writes the summary record, including `error` if the run as a whole failed. The text
+    // output only gets a summary of the failures, grouped by cause, when the run kept going
+    // past them.
+    pub fn finish(mut self, error: Option<&DorgError>) {
+        if self.output == Output::Text {
+            if let Some(DorgError::Incomplete { .. }) = error {
+                self.print_failures();
+            }
+            return;
+        }
+        // A failed file stops the run, and is already in the list
+        if let (Some(error), 0) = (error, self.counts.failed) {
+            self.failures.push(Failed {
+                path: error.path().unwrap_or(Path::new("")).to_path_buf(),
+                kind: error.kind(),
+                error: error.to_string(),
+            });
+        }
+        let errors = self.failures.iter().map(|failure| {
+            let path = Some(failure.path.as_path()).filter(|path| !path.as_os_str().is_empty());
+            format!(
+                "{{{},\"kind\":{},\"error\":{}}}",
+                path_fields("path", path),
+                string(failure.kind.name()),
+                string(&failure.error)
+            )
+        });
+        let counts = &self.counts;
+        let summary = format!(
+            "{{\"type\":\"summary\",\"planned\":{},\"done\":{},\"skipped\":{},\"failed\":{},\"errors\":[{}],\"journal\":{}}}",
+            counts.planned,
+            counts.done,
+            counts.skipped,
+            counts.failed,
+            errors.collect::<Vec<_>>().join(","),
+            self.journal.as_deref().unwrap_or("null")
+        );
+        self.write(&summary);
+        if self.output == Output::Json {
+            println!("\n]");
+        }
+    }
+
+    fn fail(&mut self, path: &Path, error: &DorgError) {
+        self.counts.failed += 1;
+        self.failures.push(Failed { path: path.to_path_buf(), kind: error.kind(), error: error.to_string() });
+    }
+
+    fn print_failures(&self) {
+        let mut groups = BTreeMap::<_, Vec<&Failed>>::new();
+        for failure in &self.failures {
+            groups.entry(failure.kind).or_default().push(failure);
+        }
+        if groups.is_empty() {
+            return;
+        }
+        eprintln!("Failed files by cause:");
+        for (kind, failures) in groups {
+            eprintln!("  {} ({}):", kind.description(), failures.len());
+            for failure in failures {
+                eprintln!("    {}", failure.error);
+            }
+        }
+    }
+
+    fn write(&mut self, record: &str) {
+        match self.output {
+            Output::Text => {}
+            Output::Ndjson => println!("{record}"),
+            Output::Json => {
+                print!("{}\n  {record}", if self.started { "," } else { "[" });
+                self.started = true;
+            }
+        }
+    }
+
+    fn text(&self, event: Event<'_>) {
+        match event {
+            Event::Planned(operation) => {
+                println!("Would {} {:?} to {:?}", operation.action.name(), operation.source, operation.destination);
+            }
+            Event::Moved { operation, destination } => {
+                println!("File {} to {:?}", operation.action.past_tense(), destination);
+            }
+            Event::Skipped { path, reason } => println!("Skipped {:?}: {reason}", path),
+            // The error names the file already
+            Event::Failed { error, .. } => eprintln!("{error}"),
+            Event::Journal(path) => println!("Journal written to {:?}", path),
+            Event::Undone { source, destination, action } => match action.keeps_source() {
+                true => println!("Removed {:?}", destination),
+                false => println!("File restored to {:?}", source),
+            },
+        }
+    }
+
+    fn record(&self, event: Event<'_>) -> Option<String> {
+        let mut record = Record::default();
+        match event {
+            Event::Planned(operation) | Event::Moved { operation, .. } => {
+                record.outcome = if matches!(event, Event::Planned(_)) { "planned" } else { "done" };
+                record.source = Some(operation.source.as_path());
+                record.destination = match event {
+                    Event::Moved { destination, .. } => Some(destination),
+                    _ => Some(operation.destination.as_path()),
+                };
+                record.date_source = Some(operation.date_source.name());
+                record.timestamp = Some(match operation.date {
+                    FileDate::Instant(instant) => self.timezone.to_rfc3339(instant),
+                    FileDate::Local(datetime) => datetime.format("%Y-%m-%dT%H:%M:%S").to_string(),
+                });
+                record.action = Some(operation.action.name());
+            }
+            Event::Skipped { path, reason } => {
+                record.outcome = "skipped";
+                record.source = Some(path);
+                record.reason = Some(reason);
+            }
+            Event::Failed { path, error } => {
+                record.outcome = "failed";
+                record.source = Some(path);
+                record.error = Some(error.to_string());
+            }
+            Event::Undone { source, destination, action } => {
+                record.outcome = "undone";
+                record.source = Some(source);
+                record.destination = Some(destination);
+                record.action = Some(action.name());
+            }
+            Event::Journal(_) => return None,
+        }
+        Some(record.to_json())
+    }
+}
+
+impl Observer for Printer {
+    fn event(&mut self, event: Event<'_>) {
+        match event {
+            // Only written for dry runs, otherwise every file would show up twice
+            Event::Planned(_) => {
+                self.counts.planned += 1;
+                if !self.dry_run {
+                    return;
+                }
+            }
+            Event::Moved { .. } | Event::Undone { .. } => self.counts.done += 1,
+            Event::Skipped { .. } => self.counts.skipped += 1,
+            Event::Failed { path, error } => self.fail(path, error),
+            Event::Journal(path) => self.journal = Some(string(&path.to_string_lossy())),
+        }
+
+        if self.output == Output::Text {
+            self.text(event);
+        } else if let Some(record) = self.record(event) {
+            self.write(&record);
+        }
+    }
+}
+
+// One file's record. Every key is always written, as `null` when it does not apply.
+#[derive(Default)]
+struct Record<'a> {
+    outcome: &'a str,
+    source: Option<&'a Path>,
+    destination: Option<&'a Path>,
+    date_source: Option<&'static str>,
+    timestamp: Option<String>,
+    action: Option<&'static str>,
+    reason: Option<&'a str>,
+    error: Option<String>,
+}
+
+impl Record<'_> {
+    fn to_json(&self) -> String {
+        let optional = |value: Option<&str>| value.map_or("null".to_string(), string);
+        format!(
+            "{{\"type\":\"file\",\"outcome\":{},{},{},\"date_source\":{},\"timestamp\":{},\"action\":{},\"reason\":{},\"error\":{}}}",
+            string(self.outcome),
+            path_fields("source", self.source),
+            path_fields("destination", self.destination),
+            optional(self.date_source),
+            optional(self.timestamp.as_deref()),
+            optional(self.action),
+            optional(self.reason),
+            optional(self.error.as_deref())
+        )
+    }
+}
+
+// `"source":"..."`. A path that is not valid UTF-8 is written with replacement characters, and
+// its exact bytes are added in hex as `"source_bytes":"..."`.
+fn path_fields(key: &str, path: Option<&Path>) -> String {
+    let Some(path) = path else {
+        return format!("\"{key}\":null");
+    };
+    match path.to_str() {
+        Some(path) => format!("\"{key}\":{}", string(path)),
+        None => format!("\"{key}\":{},\"{key}_bytes\":\"{}\"", string(&path.to_string_lossy()), hex(path)),
+    }
+}
+
+#[cfg(unix)]
+fn hex(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(windows)]
+fn hex(path: &Path) -> String {
+    use std::os::windows::ffi::OsStrExt;
+    path.as_os_str().encode_wide().map(|unit| format!("{unit:04x}")).collect()
+}
+
+// A JSON string literal.
+fn string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if u32::from(c) < 0x20 => json.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Action, SortType};
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_records() {
+        let printer = Printer::new(Output::Ndjson, Zone::Utc);
+        let operation = crate::Operation {
+            source: PathBuf::from("/in/say \"hi\".jpg"),
+            destination: PathBuf::from("/out/2024/5/say \"hi\".jpg"),
+            overwrite: false,
+            date: FileDate::Local(NaiveDate::from_ymd_opt(2024, 5, 12).unwrap().and_hms_opt(9, 30, 11).unwrap()),
+            date_source: SortType::Exif,
+            action: Action::Copy,
+        };
+        let destination = PathBuf::from("/out/2024/5/say \"hi\" (2).jpg");
+        assert_eq!(
+            printer.record(Event::Moved { operation: &operation, destination: &destination }).unwrap(),
+            r#"{"type":"file","outcome":"done","source":"/in/say \"hi\".jpg","destination":"/out/2024/5/say \"hi\" (2).jpg","date_source":"exif","timestamp":"2024-05-12T09:30:11","action":"copy","reason":null,"error":null}"#
+        );
+        assert_eq!(
+            printer.record(Event::Skipped { path: Path::new("/in/a\tb"), reason: "already in place" }).unwrap(),
+            r#"{"type":"file","outcome":"skipped","source":"/in/a\tb","destination":null,"date_source":null,"timestamp":null,"action":null,"reason":"already in place","error":null}"#
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_paths_keep_their_bytes() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = Path::new(OsStr::from_bytes(b"/in/caf\xe9.txt"));
+        assert_eq!(
+            path_fields("source", Some(path)),
+            r#""source":"/in/caf�.txt","source_bytes":"2f696e2f636166e92e747874""#
+        );
+    }
+}
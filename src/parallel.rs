@@ -0,0 +1,257 @@
+// A small worker pool for spreading per-file work over several threads while still handling
+// the results in the original order.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::hash::Hash;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Condvar, Mutex, MutexGuard};
+use std::thread;
+
+// Runs `work` on every item with up to `jobs` threads, and passes each item and its result to
+// `emit` on the calling thread in the order of `items`. No item is started more than `jobs`
+// items ahead of the last one emitted. Once `emit` returns an error no new items are started,
+// but the ones already started are still emitted, and the first error is returned.
+pub fn for_each_ordered<T, R, E>(
+    items: &[T],
+    jobs: usize,
+    work: impl Fn(&T) -> R + Sync,
+    emit: impl FnMut(&T, R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+{
+    run(items, jobs, &vec![None; items.len()], work, emit)
+}
+
+// Like `for_each_ordered`, but items with the same key are worked on one after the other, in
+// the order of `items`, as if by a single thread.
+pub fn for_each_ordered_by_key<'a, T, K, R, E>(
+    items: &'a [T],
+    jobs: usize,
+    key: impl Fn(&'a T) -> K,
+    work: impl Fn(&T) -> R + Sync,
+    emit: impl FnMut(&T, R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Sync,
+    K: Eq + Hash,
+    R: Send,
+{
+    let mut last = HashMap::new();
+    let previous: Vec<Option<usize>> =
+        items.iter().enumerate().map(|(index, item)| last.insert(key(item), index)).collect();
+    run(items, jobs, &previous, work, emit)
+}
+
+// `previous[i]` is the item that has to be finished before item `i` is started.
+fn run<T, R, E>(
+    items: &[T],
+    jobs: usize,
+    previous: &[Option<usize>],
+    work: impl Fn(&T) -> R + Sync,
+    mut emit: impl FnMut(&T, R) -> Result<(), E>,
+) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+{
+    if jobs <= 1 || items.len() <= 1 {
+        return items.iter().try_for_each(|item| emit(item, work(item)));
+    }
+
+    let finished = vec![false; items.len()];
+    let progress = Mutex::new(Progress { next: 0, emitted: 0, stop: false, panicked: false, finished });
+    let changed = Condvar::new();
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(items.len()) {
+            let sender = sender.clone();
+            let (progress, changed, work) = (&progress, &changed, &work);
+            scope.spawn(move || {
+                let _panic = StopOnPanic(progress, changed);
+                loop {
+                    let index = {
+                        let mut progress = lock(progress);
+                        while !progress.stop && progress.next >= progress.emitted + jobs {
+                            progress = changed.wait(progress).unwrap_or_else(|poisoned| poisoned.into_inner());
+                        }
+                        if progress.stop || progress.next >= items.len() {
+                            break;
+                        }
+                        let index = progress.next;
+                        progress.next += 1;
+                        if let Some(earlier) = previous[index] {
+                            while !progress.finished[earlier] && !progress.panicked {
+                                progress = changed.wait(progress).unwrap_or_else(|poisoned| poisoned.into_inner());
+                            }
+                            if progress.panicked {
+                                break;
+                            }
+                        }
+                        index
+                    };
+                    let result = work(&items[index]);
+                    lock(progress).finished[index] = true;
+                    changed.notify_all();
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Results that arrived before an earlier one, held back until it does
+        let mut waiting = BTreeMap::new();
+        let mut expected = 0;
+        let mut outcome = Ok(());
+        for (index, result) in receiver {
+            waiting.insert(index, result);
+            while let Some(result) = waiting.remove(&expected) {
+                let emitted = emit(&items[expected], result);
+                expected += 1;
+                let mut progress = lock(&progress);
+                progress.emitted = expected;
+                if let (Err(e), Ok(())) = (emitted, &outcome) {
+                    progress.stop = true;
+                    outcome = Err(e);
+                }
+                changed.notify_all();
+            }
+        }
+        outcome
+    })
+}
+
+// How far the workers of `for_each_ordered` have got.
+struct Progress {
+    // The first item no worker has started
+    next: usize,
+    emitted: usize,
+    stop: bool,
+    panicked: bool,
+    finished: Vec<bool>,
+}
+
+// Stops the other workers if `work` panics, rather than leaving them waiting for an item that
+// will never be finished or emitted.
+struct StopOnPanic<'a>(&'a Mutex<Progress>, &'a Condvar);
+
+impl Drop for StopOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            let mut progress = lock(self.0);
+            progress.stop = true;
+            progress.panicked = true;
+            self.1.notify_all();
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// Creates directories, each one only once however many files go into it or threads ask for it.
+#[derive(Default)]
+pub struct DirectoryCache {
+    created: Mutex<HashSet<PathBuf>>,
+}
+
+impl DirectoryCache {
+    pub fn create(&self, directory: &Path) -> io::Result<()> {
+        let mut created = lock(&self.created);
+        if created.contains(directory) {
+            return Ok(());
+        }
+        fs::create_dir_all(directory)?;
+        created.insert(directory.to_path_buf());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_results_are_emitted_in_order() {
+        let items: Vec<u64> = (0..50).collect();
+        for jobs in [1, 4] {
+            let mut emitted = Vec::new();
+            let result: Result<(), ()> = for_each_ordered(
+                &items,
+                jobs,
+                |item| {
+                    // Later items finish first
+                    thread::sleep(Duration::from_micros(50 - item));
+                    item * 2
+                },
+                |item, result| {
+                    emitted.push((*item, result));
+                    Ok(())
+                },
+            );
+            assert!(result.is_ok());
+            assert_eq!(emitted, items.iter().map(|item| (*item, item * 2)).collect::<Vec<_>>());
+        }
+
+        // The items already started when emitting fails are still emitted
+        let mut emitted = Vec::new();
+        let result = for_each_ordered(&items, 4, |item| *item, |item, _| {
+            emitted.push(*item);
+            if *item == 10 || *item == 12 { Err(*item) } else { Ok(()) }
+        });
+        assert_eq!(result, Err(10));
+        assert!((11..=14).contains(&emitted.len()));
+        assert_eq!(emitted, (0..emitted.len() as u64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_items_with_the_same_key_are_worked_on_in_order() {
+        let items: Vec<u64> = (0..40).collect();
+        let started = Mutex::new(Vec::new());
+        let result: Result<(), ()> = for_each_ordered_by_key(
+            &items,
+            4,
+            |item| item % 3,
+            |item| {
+                // Later items would finish first
+                thread::sleep(Duration::from_micros(400 - item * 10));
+                started.lock().unwrap().push(*item);
+            },
+            |_, _| Ok(()),
+        );
+        assert!(result.is_ok());
+        let started = started.into_inner().unwrap();
+        assert_eq!(started.len(), items.len());
+        for key in 0..3 {
+            let chain: Vec<u64> = started.iter().copied().filter(|item| item % 3 == key).collect();
+            assert!(chain.is_sorted());
+        }
+    }
+
+    #[test]
+    fn test_workers_stay_close_to_emit() {
+        let items: Vec<usize> = (0..50).collect();
+        let emitted = AtomicUsize::new(0);
+        let result: Result<(), ()> = for_each_ordered(
+            &items,
+            4,
+            |item| assert!(*item < emitted.load(Ordering::SeqCst) + 4),
+            |_, _| {
+                // A slow consumer
+                thread::sleep(Duration::from_micros(100));
+                emitted.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+        );
+        assert!(result.is_ok());
+        assert_eq!(emitted.into_inner(), items.len());
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::action::Action;
+use crate::conflict::{ConflictPolicy, Occupant};
+use crate::error::DorgError;
+use crate::{FileDate, SortType};
+
+pub struct Operation {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub overwrite: bool,
+    // The date the destination was computed from, and which date source it came from.
+    pub date: FileDate,
+    pub date_source: SortType,
+    // Usually the plan's action, unless a rule picked another one.
+    pub action: Action,
+}
+
+pub struct Skipped {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+// A file that could not be planned, with `--keep-going`. Without it planning stops at the first one.
+pub struct Failure {
+    pub path: PathBuf,
+    pub error: DorgError,
+}
+
+pub struct Plan {
+    pub directories: Vec<PathBuf>,
+    pub operations: Vec<Operation>,
+    pub skipped: Vec<Skipped>,
+    pub failed: Vec<Failure>,
+    pub on_conflict: ConflictPolicy,
+    pub action: Action,
+    seen_directories: HashSet<PathBuf>,
+    // The index of the operation each destination was claimed by.
+    claimed_destinations: HashMap<PathBuf, usize>,
+}
+
+impl Plan {
+    pub fn new(on_conflict: ConflictPolicy, action: Action) -> Plan {
+        Plan {
+            directories: Vec::new(),
+            operations: Vec::new(),
+            skipped: Vec::new(),
+            failed: Vec::new(),
+            on_conflict,
+            action,
+            seen_directories: HashSet::new(),
+            claimed_destinations: HashMap::new(),
+        }
+    }
+
+    pub fn add_directory(&mut self, directory: PathBuf) {
+        // Each directory is only looked up on disk the first time it comes up
+        if self.seen_directories.insert(directory.clone()) && !directory.exists() {
+            self.directories.push(directory);
+        }
+    }
+
+    pub fn add_operation(&mut self, operation: Operation) {
+        match self.claimed_destinations.get(&operation.destination) {
+            // With keep-newer, a file only gets a destination an earlier one claimed by being
+            // newer, so it takes that operation over and the older file stays where it is
+            Some(&index) if self.on_conflict == ConflictPolicy::KeepNewer => {
+                let overwrite = self.operations[index].overwrite;
+                let older = std::mem::replace(&mut self.operations[index], Operation { overwrite, ..operation });
+                let reason = format!("{:?} goes to {:?} and is newer", self.operations[index].source, older.destination);
+                self.skip(older.source, reason);
+            }
+            _ => {
+                self.claimed_destinations.insert(operation.destination.clone(), self.operations.len());
+                self.operations.push(operation);
+            }
+        }
+    }
+
+    pub fn skip(&mut self, path: PathBuf, reason: String) {
+        self.skipped.push(Skipped { path, reason });
+    }
+
+    pub fn fail(&mut self, path: PathBuf, error: DorgError) {
+        self.failed.push(Failure { path, error });
+    }
+
+    // What already uses a destination: an earlier operation in this plan, or else a file on disk.
+    pub fn occupant(&self, destination: &Path) -> Option<Occupant> {
+        if let Some(&index) = self.claimed_destinations.get(destination) {
+            return Some(Occupant::Planned(self.operations[index].source.clone()));
+        }
+        destination.symlink_metadata().is_ok().then_some(Occupant::Existing)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.directories.is_empty() {
+            writeln!(f, "Directories to create:")?;
+            for directory in &self.directories {
+                writeln!(f, "  {}", directory.display())?;
+            }
+        }
+        if !self.operations.is_empty() {
+            writeln!(f, "Files to {}:", self.action.name())?;
+            for operation in &self.operations {
+                let overwrite = if operation.overwrite { " (overwrite)" } else { "" };
+                let action = if operation.action != self.action {
+                    format!(" ({})", operation.action.name())
+                } else {
+                    String::new()
+                };
+                writeln!(
+                    f,
+                    "  {} -> {} [{}]{overwrite}{action}",
+                    operation.source.display(),
+                    operation.destination.display(),
+                    operation.date_source.name()
+                )?;
+            }
+        }
+        if !self.skipped.is_empty() {
+            writeln!(f, "Files to skip:")?;
+            for skipped in &self.skipped {
+                writeln!(f, "  {}: {}", skipped.path.display(), skipped.reason)?;
+            }
+        }
+        if !self.failed.is_empty() {
+            writeln!(f, "Files that failed:")?;
+            for failure in &self.failed {
+                // The error names the file already
+                writeln!(f, "  {}", failure.error)?;
+            }
+        }
+        write!(
+            f,
+            "{} file(s) to {}, {} skipped, {} new directories",
+            self.operations.len(),
+            self.action.name(),
+            self.skipped.len(),
+            self.directories.len()
+        )?;
+        if !self.failed.is_empty() {
+            write!(f, ", {} failed", self.failed.len())?;
+        }
+        writeln!(f)
+    }
+}
@@ -0,0 +1,253 @@
+// Routing rules: the first rule whose matchers all match a file decides how that file is
+// organized, in place of the global options.
+
+use std::cell::OnceCell;
+use std::fmt;
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::filter::Glob;
+use crate::{sniff, Action, Mode, SortType};
+
+pub enum Matcher {
+    // Any of the globs, matched against the path relative to the directory being organized.
+    Glob(Vec<Glob>),
+    // Any of the extensions, ignoring case.
+    Extension(Vec<String>),
+    MinSize(u64),
+    MaxSize(u64),
+    // Compared against the modification time.
+    OlderThan(Duration),
+    NewerThan(Duration),
+    // Any of the MIME types, which may end in `/*` to match a whole family like `image/*`.
+    Mime(Vec<String>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RuleAction {
+    Place(Action),
+    // Leave matching files where they are.
+    Skip,
+}
+
+pub struct Rule {
+    pub name: String,
+    pub matchers: Vec<Matcher>,
+    // Each of these falls back to the global option when not set.
+    pub mode: Option<Mode>,
+    pub date_sources: Option<Vec<SortType>>,
+    pub action: Option<RuleAction>,
+}
+
+// A file being matched against rules. Its MIME type is only worked out if a rule asks for it.
+pub struct Subject<'a> {
+    pub path: &'a Path,
+    pub relative_path: &'a Path,
+    pub metadata: &'a Metadata,
+    mime: OnceCell<Option<&'static str>>,
+}
+
+// The outcome of one matcher for a file, e.g. `extension pdf` matching `extension `pdf``.
+pub struct Check {
+    pub matched: bool,
+    pub condition: String,
+    pub actual: String,
+}
+
+impl<'a> Subject<'a> {
+    pub fn new(path: &'a Path, relative_path: &'a Path, metadata: &'a Metadata) -> Subject<'a> {
+        Subject { path, relative_path, metadata, mime: OnceCell::new() }
+    }
+
+    fn mime(&self) -> io::Result<Option<&'static str>> {
+        if let Some(mime) = self.mime.get() {
+            return Ok(*mime);
+        }
+        let mime = sniff::mime(self.path)?;
+        Ok(*self.mime.get_or_init(|| mime))
+    }
+}
+
+impl Matcher {
+    pub fn check(&self, subject: &Subject) -> io::Result<Check> {
+        let extension = || subject.path.extension().map(|ext| ext.to_string_lossy().to_lowercase());
+        let age = || {
+            let modified = subject.metadata.modified().ok()?;
+            Some(SystemTime::now().duration_since(modified).unwrap_or_default())
+        };
+        let describe_age = |age: Option<Duration>| match age {
+            Some(age) => format!("modified {} ago", format_duration(age)),
+            None => "modification time unavailable".to_string(),
+        };
+
+        let (matched, actual) = match self {
+            Matcher::Glob(globs) => (
+                globs.iter().any(|glob| glob.is_match(subject.relative_path)),
+                format!("path `{}`", subject.relative_path.display()),
+            ),
+            Matcher::Extension(extensions) => {
+                let extension = extension();
+                let matched = extension.as_ref().is_some_and(|extension| extensions.contains(extension));
+                (matched, extension.map_or("no extension".to_string(), |ext| format!("extension `{ext}`")))
+            }
+            Matcher::MinSize(size) => (subject.metadata.len() >= *size, format!("{} bytes", subject.metadata.len())),
+            Matcher::MaxSize(size) => (subject.metadata.len() <= *size, format!("{} bytes", subject.metadata.len())),
+            Matcher::OlderThan(duration) => (age().is_some_and(|age| age > *duration), describe_age(age())),
+            Matcher::NewerThan(duration) => (age().is_some_and(|age| age < *duration), describe_age(age())),
+            Matcher::Mime(patterns) => {
+                let mime = subject.mime()?;
+                let matched = mime.is_some_and(|mime| patterns.iter().any(|pattern| mime_matches(pattern, mime)));
+                (matched, mime.map_or("unknown MIME type".to_string(), |mime| format!("MIME type `{mime}`")))
+            }
+        };
+        Ok(Check { matched, condition: self.to_string(), actual })
+    }
+}
+
+impl fmt::Display for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |values: Vec<&str>| values.join(", ");
+        match self {
+            Matcher::Glob(globs) => write!(f, "glob {}", list(globs.iter().map(Glob::pattern).collect())),
+            Matcher::Extension(extensions) => {
+                write!(f, "extension {}", list(extensions.iter().map(String::as_str).collect()))
+            }
+            Matcher::MinSize(size) => write!(f, "at least {size} bytes"),
+            Matcher::MaxSize(size) => write!(f, "at most {size} bytes"),
+            Matcher::OlderThan(duration) => write!(f, "older than {}", format_duration(*duration)),
+            Matcher::NewerThan(duration) => write!(f, "newer than {}", format_duration(*duration)),
+            Matcher::Mime(patterns) => write!(f, "MIME type {}", list(patterns.iter().map(String::as_str).collect())),
+        }
+    }
+}
+
+impl Rule {
+    pub fn matches(&self, subject: &Subject) -> io::Result<bool> {
+        for matcher in &self.matchers {
+            if !matcher.check(subject)?.matched {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // Every matcher's outcome, without stopping at the first one that fails.
+    pub fn checks(&self, subject: &Subject) -> io::Result<Vec<Check>> {
+        self.matchers.iter().map(|matcher| matcher.check(subject)).collect()
+    }
+}
+
+pub fn first_match<'a>(rules: &'a [Rule], subject: &Subject) -> io::Result<Option<&'a Rule>> {
+    for rule in rules {
+        if rule.matches(subject)? {
+            return Ok(Some(rule));
+        }
+    }
+    Ok(None)
+}
+
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    match pattern.strip_suffix("/*") {
+        Some(family) => mime.split('/').next() == Some(family),
+        None => pattern.eq_ignore_ascii_case(mime),
+    }
+}
+
+// `500`, `10KB`, `1.5MB`, `2GB`. Units are powers of 1024.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let split = size.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        "T" | "TB" | "TIB" => 1 << 40,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * multiplier as f64) as u64)
+}
+
+// `30s`, `45m`, `12h`, `7d`, `2w`.
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let duration = duration.trim();
+    let unit = duration.chars().last()?;
+    let seconds: u64 = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let number: u64 = duration[..duration.len() - 1].parse().ok()?;
+    Some(Duration::from_secs(number.checked_mul(seconds)?))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        s if s >= 7 * 24 * 60 * 60 && s % (7 * 24 * 60 * 60) == 0 => format!("{}w", s / (7 * 24 * 60 * 60)),
+        s if s >= 24 * 60 * 60 => format!("{}d", s / (24 * 60 * 60)),
+        s if s >= 60 * 60 => format!("{}h", s / (60 * 60)),
+        s if s >= 60 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempdir::TempDir;
+
+    #[test]
+    fn test_parse_size_and_duration() {
+        assert_eq!(parse_size("500"), Some(500));
+        assert_eq!(parse_size("10KB"), Some(10 * 1024));
+        assert_eq!(parse_size("1.5 MB"), Some(1024 * 1024 * 3 / 2));
+        assert_eq!(parse_size("10 parsecs"), None);
+        assert_eq!(parse_duration("7d"), Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("90m"), Some(Duration::from_secs(90 * 60)));
+        assert_eq!(parse_duration("7"), None);
+        assert_eq!(format_duration(Duration::from_secs(14 * 24 * 60 * 60)), "2w");
+    }
+
+    #[test]
+    fn test_first_matching_rule() {
+        let temp_dir = TempDir::new("test_rule").expect("Failed to create temp dir");
+        let path = temp_dir.path().join("Screenshot 2024-05-12.png");
+        fs::write(&path, b"\x89PNG\r\n\x1A\n").unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        let subject = Subject::new(&path, Path::new("Screenshot 2024-05-12.png"), &metadata);
+
+        let rule = |name: &str, matchers| Rule {
+            name: name.to_string(),
+            matchers,
+            mode: None,
+            date_sources: None,
+            action: None,
+        };
+        let rules = [
+            rule("pdfs", vec![Matcher::Extension(vec!["pdf".to_string()])]),
+            rule("large images", vec![Matcher::Mime(vec!["image/*".to_string()]), Matcher::MinSize(1024)]),
+            rule("screenshots", vec![
+                Matcher::Glob(vec![Glob::new("screenshot*", true).unwrap()]),
+                Matcher::NewerThan(Duration::from_secs(60 * 60)),
+            ]),
+            rule("everything", Vec::new()),
+        ];
+
+        assert_eq!(first_match(&rules, &subject).unwrap().map(|rule| rule.name.as_str()), Some("screenshots"));
+
+        let checks = rules[1].checks(&subject).unwrap();
+        assert!(checks[0].matched);
+        assert_eq!(checks[0].actual, "MIME type `image/png`");
+        assert!(!checks[1].matched);
+        assert_eq!(checks[1].condition, "at least 1024 bytes");
+    }
+}
@@ -0,0 +1,623 @@
+// Reads `dorg.toml` configuration files. Only the part of TOML that dorg's options need is
+// supported: tables, arrays of tables, `key = value` pairs, strings, integers, booleans and
+// arrays of them.
+//
+// Each setting is turned into the command line argument it stands for, so that `Config::build`
+// is the only place options are interpreted. Rules have no command line equivalent and are
+// built here.
+
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::filter::Glob;
+use crate::rule::{self, Matcher, Rule, RuleAction};
+use crate::{Action, Mode, SortType, Template};
+
+pub const FILE_NAME: &str = "dorg.toml";
+
+// Options that are switched on with `key = true`.
+const FLAGS: [&str; 7] = ["recursive", "in-place", "dry-run", "ignore-case", "sniff", "fix-extensions", "keep-going"];
+// Options that take a single string.
+const OPTIONS: [&str; 8] = ["mode", "sort", "template", "tz", "on-conflict", "action", "dest", "output"];
+// Options that can be given several times on the command line, and take an array here.
+const LISTS: [&str; 3] = ["include", "exclude", "filename-pattern"];
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "a string",
+            Value::Integer(_) => "an integer",
+            Value::Boolean(_) => "a boolean",
+            Value::Array(_) => "an array",
+        }
+    }
+}
+
+pub struct Entry {
+    pub key: String,
+    pub value: Value,
+    pub line: usize,
+}
+
+pub struct Table {
+    // `["profile", "screenshots"]` for `[profile.screenshots]`, empty for the top level.
+    pub path: Vec<String>,
+    // Whether this is one element of an array of tables, like `[[rule]]`.
+    pub array: bool,
+    pub line: usize,
+    pub entries: Vec<Entry>,
+}
+
+pub struct Settings {
+    // Each with the line of the setting it comes from
+    pub args: Vec<(String, usize)>,
+    pub rules: Vec<Rule>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// Where the configuration file is looked for when none is given with `--config`: the directory
+// being organized, then `$XDG_CONFIG_HOME/dorg` (or `~/.config/dorg`).
+pub fn find(directory: &Path) -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    [Some(directory.join(FILE_NAME)), config_home.map(|dir| dir.join("dorg").join(FILE_NAME))]
+        .into_iter()
+        .flatten()
+        .find(|path| path.is_file())
+}
+
+// Reads the top-level settings of a configuration file followed by those of `profile`, as
+// command line arguments, and the rules of `profile` followed by the top-level ones.
+pub fn load(path: &Path, profile: Option<&str>) -> Result<Settings, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Error reading {:?}: {e}", path))?;
+    let tables = parse(&text).map_err(|e| format!("Invalid configuration {:?}, {e}", path))?;
+    if let Some(profile) = profile.filter(|profile| !has_profile(&tables, profile)) {
+        return Err(format!("No profile named `{profile}` in {:?}", path));
+    }
+    let base = path.parent().unwrap_or(Path::new("."));
+    to_settings(&tables, profile, base).map_err(|e| format!("Invalid configuration {:?}, {e}", path))
+}
+
+fn has_profile(tables: &[Table], profile: &str) -> bool {
+    tables
+        .iter()
+        .any(|table| matches!(table.path.as_slice(), [first, name, ..] if first == "profile" && name == profile))
+}
+
+fn to_settings(tables: &[Table], profile: Option<&str>, base: &Path) -> Result<Settings, ParseError> {
+    let mut defaults = Settings { args: Vec::new(), rules: Vec::new() };
+    let mut selected = Settings { args: Vec::new(), rules: Vec::new() };
+    // Profiles that are not selected are still checked
+    let mut ignored = Settings { args: Vec::new(), rules: Vec::new() };
+    // Unnamed rules are called after their position in the file
+    let mut rule_count = 0;
+
+    for table in tables {
+        let path: Vec<&str> = table.path.iter().map(String::as_str).collect();
+        let (settings, rest) = match path.as_slice() {
+            ["profile", name, rest @ ..] if profile == Some(*name) => (&mut selected, rest),
+            ["profile", _, rest @ ..] => (&mut ignored, rest),
+            rest => (&mut defaults, rest),
+        };
+        match (rest, table.array) {
+            ([], false) => settings_to_args(&table.entries, base, &mut settings.args)?,
+            (["types"], false) => types_to_args(&table.entries, &mut settings.args)?,
+            (["rule"], true) => {
+                rule_count += 1;
+                settings.rules.push(to_rule(table, rule_count)?);
+            }
+            _ => return Err(unknown_table(table)),
+        }
+    }
+
+    defaults.args.extend(selected.args);
+    selected.rules.extend(defaults.rules);
+    Ok(Settings { args: defaults.args, rules: selected.rules })
+}
+
+fn unknown_table(table: &Table) -> ParseError {
+    let path = table.path.join(".");
+    let header = if table.array { format!("[[{path}]]") } else { format!("[{path}]") };
+    ParseError { line: table.line, message: format!("unknown table `{header}`") }
+}
+
+fn to_rule(table: &Table, number: usize) -> Result<Rule, ParseError> {
+    let mut rule = Rule {
+        name: format!("rule {number}"),
+        matchers: Vec::new(),
+        mode: None,
+        date_sources: None,
+        action: None,
+    };
+    let ignore_case = table
+        .entries
+        .iter()
+        .any(|entry| entry.key == "ignore-case" && entry.value == Value::Boolean(true));
+
+    for entry in &table.entries {
+        let error = |message: String| ParseError { line: entry.line, message };
+        let invalid = |what: &str| error(format!("invalid {what} `{}`", display(&entry.value)));
+        let string = || match &entry.value {
+            Value::String(value) => Ok(value.as_str()),
+            value => Err(error(format!("`{}` cannot be {}", entry.key, value.type_name()))),
+        };
+        let size = || match &entry.value {
+            Value::Integer(size) => u64::try_from(*size).map_err(|_| invalid("size")),
+            Value::String(size) => rule::parse_size(size).ok_or_else(|| invalid("size")),
+            value => Err(error(format!("`{}` cannot be {}", entry.key, value.type_name()))),
+        };
+        let duration = || string().and_then(|value| rule::parse_duration(value).ok_or_else(|| invalid("duration")));
+
+        match entry.key.as_str() {
+            "name" => rule.name = string()?.to_string(),
+            "ignore-case" => {}
+            "glob" => {
+                let globs = strings(&entry.value)
+                    .map_err(error)?
+                    .iter()
+                    .map(|pattern| Glob::new(pattern, ignore_case))
+                    .collect::<Result<_, _>>()
+                    .map_err(error)?;
+                rule.matchers.push(Matcher::Glob(globs));
+            }
+            "extension" => {
+                let extensions = strings(&entry.value).map_err(error)?;
+                let extensions = extensions.iter().map(|ext| ext.trim_start_matches('.').to_lowercase()).collect();
+                rule.matchers.push(Matcher::Extension(extensions));
+            }
+            "mime" => rule.matchers.push(Matcher::Mime(strings(&entry.value).map_err(error)?)),
+            "min-size" => rule.matchers.push(Matcher::MinSize(size()?)),
+            "max-size" => rule.matchers.push(Matcher::MaxSize(size()?)),
+            "older-than" => rule.matchers.push(Matcher::OlderThan(duration()?)),
+            "newer-than" => rule.matchers.push(Matcher::NewerThan(duration()?)),
+            "mode" => rule.mode = Some(Mode::parse(string()?).ok_or_else(|| invalid("mode"))?),
+            "template" => {
+                let template = Template::parse(string()?).map_err(|e| error(format!("invalid template: {e}")))?;
+                rule.mode = Some(Mode::Template(template));
+            }
+            "date-source" => {
+                let sources = strings(&entry.value).map_err(error)?;
+                let sources = sources
+                    .iter()
+                    .map(|source| SortType::parse(source).ok_or_else(|| error(format!("invalid date source `{source}`"))))
+                    .collect::<Result<_, _>>()?;
+                rule.date_sources = Some(sources);
+            }
+            "action" => {
+                let action = match string()? {
+                    "skip" => RuleAction::Skip,
+                    action => RuleAction::Place(Action::parse(action).ok_or_else(|| invalid("action"))?),
+                };
+                rule.action = Some(action);
+            }
+            key => return Err(error(format!("unknown key `{key}` in a rule"))),
+        }
+    }
+    Ok(rule)
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        Value::Integer(value) => value.to_string(),
+        Value::Boolean(value) => value.to_string(),
+        Value::Array(values) => format!("[{}]", values.iter().map(display).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+fn settings_to_args(entries: &[Entry], base: &Path, args: &mut Vec<(String, usize)>) -> Result<(), ParseError> {
+    for entry in entries {
+        let mut push = |arg: String| args.push((arg, entry.line));
+        let error = |message: String| ParseError { line: entry.line, message };
+        let wrong_type = || error(format!("`{}` cannot be {}", entry.key, entry.value.type_name()));
+        let key = entry.key.as_str();
+
+        match key {
+            key if FLAGS.contains(&key) => match entry.value {
+                Value::Boolean(true) if key == "recursive" => push("-r".to_string()),
+                Value::Boolean(true) => push(format!("--{key}")),
+                Value::Boolean(false) => {}
+                _ => return Err(wrong_type()),
+            },
+            "max-depth" | "jobs" => match entry.value {
+                Value::Integer(number) => push(format!("--{key}={number}")),
+                _ => return Err(wrong_type()),
+            },
+            key if OPTIONS.contains(&key) => {
+                let Value::String(value) = &entry.value else {
+                    return Err(wrong_type());
+                };
+                match key {
+                    "mode" | "sort" => push(format!("-{key}={value}")),
+                    // Relative to the configuration file rather than wherever dorg is run from
+                    "dest" => push(format!("--dest={}", base.join(value).display())),
+                    _ => push(format!("--{key}={value}")),
+                }
+            }
+            "date-source" => {
+                let sources = strings(&entry.value).map_err(error)?;
+                push(format!("--date-source={}", sources.join(",")));
+            }
+            key if LISTS.contains(&key) => {
+                let values = strings(&entry.value).map_err(error)?;
+                values.iter().for_each(|value| push(format!("--{key}={value}")));
+            }
+            _ => return Err(error(format!("unknown key `{key}`"))),
+        }
+    }
+    Ok(())
+}
+
+// `Images = ["jpg", "png"]` becomes `--type-group=Images=jpg,png`.
+fn types_to_args(entries: &[Entry], args: &mut Vec<(String, usize)>) -> Result<(), ParseError> {
+    for entry in entries {
+        let extensions = strings(&entry.value).map_err(|message| ParseError { line: entry.line, message })?;
+        args.push((format!("--type-group={}={}", entry.key, extensions.join(",")), entry.line));
+    }
+    Ok(())
+}
+
+// A string, or an array of strings.
+fn strings(value: &Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::String(value) => Ok(vec![value.clone()]),
+        Value::Array(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::String(value) => Ok(value.clone()),
+                value => Err(format!("expected a string, found {}", value.type_name())),
+            })
+            .collect(),
+        value => Err(format!("expected a string or an array of strings, found {}", value.type_name())),
+    }
+}
+
+pub fn parse(text: &str) -> Result<Vec<Table>, ParseError> {
+    let mut parser = Parser { chars: text.chars().collect(), position: 0, line: 1 };
+    let mut tables = vec![Table { path: Vec::new(), array: false, line: 1, entries: Vec::new() }];
+
+    loop {
+        parser.skip_whitespace_and_comments(true);
+        let Some(c) = parser.peek() else { break };
+        let line = parser.line;
+
+        if c == '[' {
+            parser.next();
+            let array = parser.peek() == Some('[');
+            if array {
+                parser.next();
+            }
+            parser.skip_whitespace_and_comments(false);
+            let path = parser.key_path()?;
+            parser.expect(']')?;
+            if array {
+                parser.expect(']')?;
+            }
+            if tables.iter().any(|table| table.path == path && !(table.array && array)) {
+                return Err(ParseError { line, message: format!("table `{}` is defined twice", path.join(".")) });
+            }
+            tables.push(Table { path, array, line, entries: Vec::new() });
+        } else {
+            let key = parser.key()?;
+            parser.expect('=')?;
+            parser.skip_whitespace_and_comments(false);
+            let value = parser.value()?;
+            let table = tables.last_mut().unwrap();
+            if table.entries.iter().any(|entry| entry.key == key) {
+                return Err(ParseError { line, message: format!("key `{key}` is defined twice") });
+            }
+            table.entries.push(Entry { key, value, line });
+        }
+        parser.end_of_line()?;
+    }
+    Ok(tables)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += 1;
+        if c == '\n' {
+            self.line += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: String) -> ParseError {
+        ParseError { line: self.line, message }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace_and_comments(false);
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected `{expected}`, found `{c}`"))),
+            None => Err(self.error(format!("expected `{expected}`, found the end of the file"))),
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => {}
+                '#' => {
+                    while self.peek().is_some_and(|c| c != '\n') {
+                        self.next();
+                    }
+                    continue;
+                }
+                _ => break,
+            }
+            self.next();
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), ParseError> {
+        self.skip_whitespace_and_comments(false);
+        match self.peek() {
+            None => Ok(()),
+            Some('\n') => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("unexpected `{c}` after a value"))),
+        }
+    }
+
+    fn key_path(&mut self) -> Result<Vec<String>, ParseError> {
+        let mut path = vec![self.key()?];
+        loop {
+            self.skip_whitespace_and_comments(false);
+            if self.peek() != Some('.') {
+                return Ok(path);
+            }
+            self.next();
+            self.skip_whitespace_and_comments(false);
+            path.push(self.key()?);
+        }
+    }
+
+    fn key(&mut self) -> Result<String, ParseError> {
+        if matches!(self.peek(), Some('"' | '\'')) {
+            return self.string();
+        }
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            self.next();
+        }
+        if start == self.position {
+            return Err(self.error("expected a key".to_string()));
+        }
+        Ok(self.chars[start..self.position].iter().collect())
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        match self.peek() {
+            Some('"' | '\'') => self.string().map(Value::String),
+            Some('[') => self.array(),
+            Some(c) if c.is_ascii_alphanumeric() || c == '-' || c == '+' => {
+                let start = self.position;
+                while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '_')) {
+                    self.next();
+                }
+                let word: String = self.chars[start..self.position].iter().collect();
+                match word.as_str() {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    word => word
+                        .replace('_', "")
+                        .parse()
+                        .map(Value::Integer)
+                        .map_err(|_| self.error(format!("invalid value `{word}`"))),
+                }
+            }
+            _ => Err(self.error("expected a value".to_string())),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, ParseError> {
+        self.next();
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments(true);
+            if self.peek() == Some(']') {
+                self.next();
+                return Ok(Value::Array(values));
+            }
+            values.push(self.value()?);
+            self.skip_whitespace_and_comments(true);
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                Some(']') => {}
+                _ => return Err(self.error("expected `,` or `]` in array".to_string())),
+            }
+        }
+    }
+
+    // Basic `"..."` strings with escapes, and literal `'...'` strings without.
+    fn string(&mut self) -> Result<String, ParseError> {
+        let quote = self.next().unwrap();
+        let mut string = String::new();
+        loop {
+            let c = match self.peek() {
+                None | Some('\n') => return Err(self.error("unterminated string".to_string())),
+                Some(_) => self.next().unwrap(),
+            };
+            match c {
+                c if c == quote => return Ok(string),
+                '\\' if quote == '"' => {
+                    let escaped = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some(c @ ('u' | 'U')) => {
+                            let digits = if c == 'u' { 4 } else { 8 };
+                            let hex: String = (0..digits).filter_map(|_| self.next()).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error(format!("invalid unicode escape `\\{c}{hex}`")))?
+                        }
+                        c => {
+                            let c = c.map(String::from).unwrap_or_default();
+                            return Err(self.error(format!("invalid escape `\\{c}`")));
+                        }
+                    };
+                    string.push(escaped);
+                }
+                c => string.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+# Defaults for every run
+recursive = true
+date-source = ["exif", "created"]
+exclude = ['desktop.ini', ".DS_Store"]
+
+[types]
+Raw = ["cr2", "nef"]
+
+[profile.screenshots]
+mode = "day" # one directory per day
+sort = "filename"
+dest = "sorted"
+max-depth = 2
+
+[profile.downloads]
+mode = "type"
+"#;
+
+    fn settings(text: &str, profile: Option<&str>) -> Result<Settings, String> {
+        let tables = parse(text).map_err(|e| e.to_string())?;
+        to_settings(&tables, profile, Path::new("/config")).map_err(|e| e.to_string())
+    }
+
+    fn args(text: &str, profile: Option<&str>) -> Result<Vec<String>, String> {
+        settings(text, profile).map(|settings| settings.args.into_iter().map(|(arg, _)| arg).collect())
+    }
+
+    #[test]
+    fn test_settings_become_arguments() {
+        assert_eq!(
+            args(CONFIG, Some("screenshots")).unwrap(),
+            vec![
+                "-r",
+                "--date-source=exif,created",
+                "--exclude=desktop.ini",
+                "--exclude=.DS_Store",
+                "--type-group=Raw=cr2,nef",
+                "-mode=day",
+                "-sort=filename",
+                "--dest=/config/sorted",
+                "--max-depth=2",
+            ]
+        );
+        assert_eq!(args(CONFIG, None).unwrap().len(), 5);
+        assert!(has_profile(&parse(CONFIG).unwrap(), "downloads"));
+        assert!(!has_profile(&parse(CONFIG).unwrap(), "photos"));
+    }
+
+    #[test]
+    fn test_errors_have_line_numbers() {
+        let error = |text| args(text, None).unwrap_err();
+        assert_eq!(error("recursive = true\nrecursve = true\n"), "line 2: unknown key `recursve`");
+        assert_eq!(error("\n[profile.a]\nmode = 'day'\ncolour = 1"), "line 4: unknown key `colour`");
+        assert_eq!(error("[profiles]\n"), "line 1: unknown table `[profiles]`");
+        assert_eq!(error("mode = 2"), "line 1: `mode` cannot be an integer");
+        assert_eq!(error("mode = \"day\nsort = 'exif'"), "line 1: unterminated string");
+        assert_eq!(error("include = [\n  '*.jpg',\n  3,\n]"), "line 1: expected a string, found an integer");
+        assert_eq!(error("a = 1\na = 2"), "line 2: key `a` is defined twice");
+        assert_eq!(error("mode = 'day' 'month'"), "line 1: unexpected `'` after a value");
+        assert_eq!(error("[[rule]]\nglob = '*.png'\ncolor = 'red'"), "line 3: unknown key `color` in a rule");
+        assert_eq!(error("[[rule]]\nmin-size = '10 parsecs'"), "line 2: invalid size `10 parsecs`");
+        assert_eq!(error("[[rules]]"), "line 1: unknown table `[[rules]]`");
+    }
+
+    #[test]
+    fn test_rules() {
+        let text = r#"
+[[rule]]
+name = "screenshots"
+glob = "Screenshot*.png"
+ignore-case = true
+mode = "day"
+date-source = ["filename", "modified"]
+
+[[rule]]
+extension = [".PDF"]
+mode = "type"
+action = "copy"
+
+[[rule]]
+min-size = "1GB"
+action = "skip"
+
+[[profile.camera.rule]]
+mime = "image/*"
+template = "{year}/{name}"
+"#;
+        let rules = settings(text, None).unwrap().rules;
+        assert_eq!(rules.len(), 3);
+        assert_eq!(rules[0].name, "screenshots");
+        assert!(matches!(rules[0].mode, Some(Mode::Day)));
+        assert_eq!(rules[0].date_sources, Some(vec![SortType::Filename, SortType::Modified]));
+        assert_eq!(rules[1].name, "rule 2");
+        assert_eq!(rules[1].matchers[0].to_string(), "extension pdf");
+        assert_eq!(rules[1].action, Some(RuleAction::Place(Action::Copy)));
+        assert_eq!(rules[2].matchers[0].to_string(), "at least 1073741824 bytes");
+        assert_eq!(rules[2].action, Some(RuleAction::Skip));
+
+        let camera = settings(text, Some("camera")).unwrap().rules;
+        assert_eq!(camera.len(), 4);
+        assert_eq!(camera[3].name, "rule 3");
+        assert!(matches!(camera[0].mode, Some(Mode::Template(_))));
+    }
+}
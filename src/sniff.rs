@@ -0,0 +1,260 @@
+// Recognises common file formats by their leading magic bytes, for files whose extension is
+// missing or wrong.
+
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::bmff;
+
+// How much of the start of a file is looked at. Enough to find the first entries of a ZIP file.
+const HEADER_LEN: u64 = 4096;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Format {
+    pub mime: &'static str,
+    // Extensions this format is commonly saved with, the usual one first. Empty for formats
+    // that are normally saved without one, like ELF executables.
+    pub extensions: &'static [&'static str],
+}
+
+impl Format {
+    const fn new(mime: &'static str, extensions: &'static [&'static str]) -> Format {
+        Format { mime, extensions }
+    }
+
+    pub fn extension(&self) -> Option<&'static str> {
+        self.extensions.first().copied()
+    }
+
+    // Whether `extension` is one this format is commonly saved with, ignoring case.
+    pub fn accepts(&self, extension: &str) -> bool {
+        self.extensions.iter().any(|ext| ext.eq_ignore_ascii_case(extension))
+    }
+}
+
+const JPEG: Format = Format::new("image/jpeg", &["jpg", "jpeg", "jpe", "jfif"]);
+const PNG: Format = Format::new("image/png", &["png"]);
+const GIF: Format = Format::new("image/gif", &["gif"]);
+const WEBP: Format = Format::new("image/webp", &["webp"]);
+// RAW formats from most cameras are TIFF files too
+const TIFF: Format = Format::new("image/tiff", &["tif", "tiff", "dng", "nef", "arw", "cr2", "orf", "pef", "srw"]);
+const HEIC: Format = Format::new("image/heic", &["heic", "heif"]);
+const AVIF: Format = Format::new("image/avif", &["avif"]);
+const CR3: Format = Format::new("image/x-canon-cr3", &["cr3"]);
+const PDF: Format = Format::new("application/pdf", &["pdf"]);
+const DOCX: Format = Format::new("application/vnd.openxmlformats-officedocument.wordprocessingml.document", &["docx"]);
+const XLSX: Format = Format::new("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet", &["xlsx"]);
+const PPTX: Format = Format::new("application/vnd.openxmlformats-officedocument.presentationml.presentation", &["pptx"]);
+const ODT: Format = Format::new("application/vnd.oasis.opendocument.text", &["odt"]);
+const ODS: Format = Format::new("application/vnd.oasis.opendocument.spreadsheet", &["ods"]);
+const ODP: Format = Format::new("application/vnd.oasis.opendocument.presentation", &["odp"]);
+const EPUB: Format = Format::new("application/epub+zip", &["epub"]);
+// Plenty of formats are ZIP files, so a ZIP that is not recognised as one of the above is
+// left alone when it already has one of their extensions
+const ZIP: Format = Format::new(
+    "application/zip",
+    &["zip", "docx", "xlsx", "pptx", "odt", "ods", "odp", "epub", "jar", "apk", "cbz", "xpi", "ipa"],
+);
+const GZIP: Format = Format::new("application/gzip", &["gz", "tgz"]);
+const SEVEN_ZIP: Format = Format::new("application/x-7z-compressed", &["7z"]);
+const RAR: Format = Format::new("application/vnd.rar", &["rar", "cbr"]);
+const XZ: Format = Format::new("application/x-xz", &["xz", "txz"]);
+const ZSTD: Format = Format::new("application/zstd", &["zst"]);
+const BZIP2: Format = Format::new("application/x-bzip2", &["bz2", "tbz2"]);
+const MP4: Format = Format::new("video/mp4", &["mp4", "m4v", "m4a", "m4b"]);
+const M4A: Format = Format::new("audio/mp4", &["m4a", "m4b"]);
+const MOV: Format = Format::new("video/quicktime", &["mov", "qt"]);
+const THREE_GP: Format = Format::new("video/3gpp", &["3gp", "3g2"]);
+const MATROSKA: Format = Format::new("video/x-matroska", &["mkv", "webm", "mka"]);
+const AVI: Format = Format::new("video/x-msvideo", &["avi"]);
+const MP3: Format = Format::new("audio/mpeg", &["mp3"]);
+const FLAC: Format = Format::new("audio/flac", &["flac"]);
+const OGG: Format = Format::new("audio/ogg", &["ogg", "opus", "oga", "ogv"]);
+const WAV: Format = Format::new("audio/wav", &["wav"]);
+const ELF: Format = Format::new("application/x-executable", &[]);
+
+// Looked up by extension for files whose contents are not recognised, more specific formats
+// first. The text formats at the end have no magic bytes.
+const BY_EXTENSION: [Format; 40] = [
+    JPEG, PNG, GIF, WEBP, TIFF, HEIC, AVIF, CR3, PDF, DOCX, XLSX, PPTX, ODT, ODS, ODP, EPUB, ZIP, GZIP,
+    SEVEN_ZIP, RAR, XZ, ZSTD, BZIP2, M4A, MP4, MOV, THREE_GP, MATROSKA, AVI, MP3, FLAC, OGG, WAV,
+    Format::new("text/plain", &["txt", "log"]),
+    Format::new("text/markdown", &["md"]),
+    Format::new("text/csv", &["csv"]),
+    Format::new("text/html", &["html", "htm"]),
+    Format::new("application/json", &["json"]),
+    Format::new("application/xml", &["xml"]),
+    Format::new("image/svg+xml", &["svg"]),
+];
+
+// The MIME type of a file from its contents, or else its extension.
+pub fn mime(path: &Path) -> io::Result<Option<&'static str>> {
+    if let Some(format) = format(path)? {
+        return Ok(Some(format.mime));
+    }
+    let Some(extension) = path.extension() else {
+        return Ok(None);
+    };
+    let extension = extension.to_string_lossy();
+    Ok(BY_EXTENSION.iter().find(|format| format.accepts(&extension)).map(|format| format.mime))
+}
+
+// Returns `None` for files that are not in any of the recognised formats.
+pub fn format(path: &Path) -> io::Result<Option<Format>> {
+    let mut header = Vec::new();
+    File::open(path)?.take(HEADER_LEN).read_to_end(&mut header)?;
+    Ok(detect(&header))
+}
+
+// Returns the file name with the usual extension of `format`, or `None` if it already has one of
+// its extensions. An extension that does not look like one, such as the `2024` in `backup.2024`,
+// is kept and the right one appended.
+pub fn fixed_name(name: &OsStr, format: Format) -> Option<OsString> {
+    let path = Path::new(name);
+    let extension = path.extension().map(|ext| ext.to_string_lossy());
+    if extension.as_deref().is_some_and(|ext| format.accepts(ext)) {
+        return None;
+    }
+    let correct = format.extension()?;
+
+    let looks_like_extension = |ext: &str| {
+        (1..=4).contains(&ext.len())
+            && ext.chars().all(|c| c.is_ascii_alphanumeric())
+            && !ext.chars().all(|c| c.is_ascii_digit())
+    };
+    let mut fixed = match extension {
+        Some(ext) if looks_like_extension(&ext) => path.file_stem()?.to_owned(),
+        _ => name.to_owned(),
+    };
+    fixed.push(".");
+    fixed.push(correct);
+    Some(fixed)
+}
+
+fn detect(header: &[u8]) -> Option<Format> {
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+
+    let format = if at(0, b"\xFF\xD8\xFF") {
+        JPEG
+    } else if at(0, b"\x89PNG\r\n\x1A\n") {
+        PNG
+    } else if at(0, b"GIF87a") || at(0, b"GIF89a") {
+        GIF
+    } else if at(0, b"II*\0") || at(0, b"MM\0*") {
+        TIFF
+    } else if at(0, b"RIFF") && at(8, b"WEBP") {
+        WEBP
+    } else if at(0, b"RIFF") && at(8, b"WAVE") {
+        WAV
+    } else if at(0, b"RIFF") && at(8, b"AVI ") {
+        AVI
+    } else if bmff::is_bmff(header) {
+        from_brand(header.get(8..12)?)
+    } else if at(0, b"%PDF-") {
+        PDF
+    } else if at(0, b"PK\x03\x04") {
+        from_zip(header)
+    } else if at(0, b"\x1F\x8B") {
+        GZIP
+    } else if at(0, b"7z\xBC\xAF\x27\x1C") {
+        SEVEN_ZIP
+    } else if at(0, b"Rar!\x1A\x07") {
+        RAR
+    } else if at(0, b"\xFD7zXZ\0") {
+        XZ
+    } else if at(0, b"\x28\xB5\x2F\xFD") {
+        ZSTD
+    } else if at(0, b"BZh") {
+        BZIP2
+    } else if at(0, b"\x1A\x45\xDF\xA3") {
+        MATROSKA
+    } else if at(0, b"ID3") || at(0, b"\xFF\xFB") || at(0, b"\xFF\xF3") || at(0, b"\xFF\xF2") {
+        MP3
+    } else if at(0, b"fLaC") {
+        FLAC
+    } else if at(0, b"OggS") {
+        OGG
+    } else if at(0, b"\x7FELF") {
+        ELF
+    } else {
+        return None;
+    };
+    Some(format)
+}
+
+// Tells ISO base media files apart by the major brand in their `ftyp` box.
+fn from_brand(brand: &[u8]) -> Format {
+    match brand {
+        b"heic" | b"heix" | b"hevc" | b"heim" | b"heis" | b"mif1" | b"msf1" => HEIC,
+        b"avif" | b"avis" => AVIF,
+        b"crx " => CR3,
+        b"qt  " => MOV,
+        b"M4A " | b"M4B " => M4A,
+        brand if brand.starts_with(b"3g") => THREE_GP,
+        _ => MP4,
+    }
+}
+
+// Office and OpenDocument files are ZIP archives. OpenDocument and EPUB files start with an
+// uncompressed `mimetype` entry, and Office files have their main part near the start.
+fn from_zip(header: &[u8]) -> Format {
+    let contains = |needle: &[u8]| header.windows(needle.len()).any(|window| window == needle);
+
+    if contains(b"mimetypeapplication/epub+zip") {
+        EPUB
+    } else if contains(b"mimetypeapplication/vnd.oasis.opendocument.text") {
+        ODT
+    } else if contains(b"mimetypeapplication/vnd.oasis.opendocument.spreadsheet") {
+        ODS
+    } else if contains(b"mimetypeapplication/vnd.oasis.opendocument.presentation") {
+        ODP
+    } else if contains(b"word/") {
+        DOCX
+    } else if contains(b"xl/") {
+        XLSX
+    } else if contains(b"ppt/") {
+        PPTX
+    } else {
+        ZIP
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_formats() {
+        assert_eq!(detect(b"\xFF\xD8\xFF\xE1\0\0Exif"), Some(JPEG));
+        assert_eq!(detect(b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR"), Some(PNG));
+        assert_eq!(detect(b"RIFF\0\0\0\0WEBPVP8 "), Some(WEBP));
+        assert_eq!(detect(b"\0\0\0\x18ftypheic\0\0\0\0"), Some(HEIC));
+        assert_eq!(detect(b"\0\0\0\x18ftypisom\0\0\0\0"), Some(MP4));
+        assert_eq!(detect(b"%PDF-1.7\n"), Some(PDF));
+        let docx = b"PK\x03\x04\x14\0\0\0\0\0[Content_Types].xmlPK\x03\x04\x14\0\0\0\0\0word/document.xml";
+        assert_eq!(detect(docx), Some(DOCX));
+        assert_eq!(detect(b"PK\x03\x04\x14\0\0\0\0\0notes.txt"), Some(ZIP));
+        assert_eq!(detect(b"\x7FELF\x02\x01\x01"), Some(ELF));
+        assert_eq!(detect(b"ID3\x04\0"), Some(MP3));
+        assert_eq!(detect(b"\x1F\x8B\x08\0"), Some(GZIP));
+        assert_eq!(detect(b"Hello, world"), None);
+        assert_eq!(detect(b""), None);
+
+        assert!(TIFF.accepts("NEF"));
+        assert!(!JPEG.accepts("bin"));
+        assert_eq!(ELF.extension(), None);
+    }
+
+    #[test]
+    fn test_fixed_name() {
+        let fixed = |name: &str, format| fixed_name(OsStr::new(name), format);
+        assert_eq!(fixed("export.bin", JPEG), Some(OsString::from("export.jpg")));
+        assert_eq!(fixed("IMG_1", PNG), Some(OsString::from("IMG_1.png")));
+        assert_eq!(fixed("backup.2024", ZIP), Some(OsString::from("backup.2024.zip")));
+        assert_eq!(fixed("photo.JPEG", JPEG), None);
+        assert_eq!(fixed("DSC_1.nef", TIFF), None);
+        assert_eq!(fixed("a.out", ELF), None);
+    }
+}
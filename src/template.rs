@@ -0,0 +1,252 @@
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+
+const PLACEHOLDERS: [&str; 15] = [
+    "year", "month", "day", "hour", "minute", "second", "month_name", "month_abbr", "weekday",
+    "weekday_abbr", "name", "stem", "ext", "dir", "type",
+];
+const NUMERIC_PLACEHOLDERS: [&str; 6] = ["year", "month", "day", "hour", "minute", "second"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TemplateError {
+    Empty,
+    UnclosedBrace(usize),
+    UnexpectedBrace(usize),
+    UnknownPlaceholder(String),
+    InvalidFormat(String, String),
+    AbsolutePath,
+    ParentDirectory,
+    MissingFileName,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::Empty => write!(f, "Template is empty"),
+            TemplateError::UnclosedBrace(position) => {
+                write!(f, "Unclosed `{{` at position {position}, use `{{{{` for a literal brace")
+            }
+            TemplateError::UnexpectedBrace(position) => {
+                write!(f, "Unexpected `}}` at position {position}, use `}}}}` for a literal brace")
+            }
+            TemplateError::UnknownPlaceholder(name) => write!(
+                f,
+                "Unknown placeholder `{{{name}}}`, expected one of: {}",
+                PLACEHOLDERS.join(", ")
+            ),
+            TemplateError::InvalidFormat(name, format) => write!(
+                f,
+                "Invalid format `{format}` for `{{{name}}}`, only numeric placeholders can be zero-padded (e.g. `{{month:02}}`)"
+            ),
+            TemplateError::AbsolutePath => write!(f, "Template must be a relative path"),
+            TemplateError::ParentDirectory => write!(f, "Template must not contain `..`"),
+            TemplateError::MissingFileName => write!(f, "Template must end with a file name, not `/`"),
+        }
+    }
+}
+
+impl Error for TemplateError {}
+
+enum Token {
+    Literal(String),
+    Placeholder { name: &'static str, width: Option<usize> },
+}
+
+// A destination layout such as `{year}/{month:02}-{month_name}/{stem}.{ext}`, relative to the
+// destination directory. Each `/`-separated segment becomes one directory level, and empty
+// segments (e.g. `{dir}` for files at the top level) are dropped.
+pub struct Template {
+    segments: Vec<Vec<Token>>,
+}
+
+pub struct TemplateContext<'a> {
+    pub datetime: NaiveDateTime,
+    // Path of the file relative to the directory being organized.
+    pub relative_path: &'a Path,
+    // Type category of the file, such as `Images`.
+    pub category: &'a str,
+}
+
+impl Template {
+    pub fn parse(pattern: &str) -> Result<Template, TemplateError> {
+        if pattern.is_empty() {
+            return Err(TemplateError::Empty);
+        }
+        if pattern.starts_with('/') {
+            return Err(TemplateError::AbsolutePath);
+        }
+        if pattern.ends_with('/') {
+            return Err(TemplateError::MissingFileName);
+        }
+
+        let mut segments = vec![Vec::new()];
+        let mut literal = String::new();
+        let mut chars = pattern.char_indices().peekable();
+
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|&(_, c)| c == '{').is_some() => literal.push('{'),
+                '}' if chars.next_if(|&(_, c)| c == '}').is_some() => literal.push('}'),
+                '}' => return Err(TemplateError::UnexpectedBrace(position)),
+                '{' => {
+                    let mut inner = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => inner.push(c),
+                            None => return Err(TemplateError::UnclosedBrace(position)),
+                        }
+                    }
+                    let segment = segments.last_mut().unwrap();
+                    if !literal.is_empty() {
+                        segment.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    segment.push(parse_placeholder(&inner)?);
+                }
+                '/' => {
+                    let segment = segments.last_mut().unwrap();
+                    if !literal.is_empty() {
+                        segment.push(Token::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Vec::new());
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.last_mut().unwrap().push(Token::Literal(literal));
+        }
+
+        let is_parent = |segment: &Vec<Token>| matches!(segment.as_slice(), [Token::Literal(text)] if text == "..");
+        if segments.iter().any(is_parent) {
+            return Err(TemplateError::ParentDirectory);
+        }
+
+        Ok(Template { segments })
+    }
+
+    // Fails with the reason if the template renders to an empty file name for this file, or
+    // to a `.` or `..` that would lead outside the destination directory.
+    pub fn render(&self, context: &TemplateContext) -> Result<PathBuf, &'static str> {
+        let mut path = PathBuf::new();
+        let mut file_name = OsString::new();
+
+        for segment in &self.segments {
+            file_name = OsString::new();
+            for token in segment {
+                match token {
+                    Token::Literal(text) => file_name.push(text),
+                    Token::Placeholder { name, width } => file_name.push(value(name, *width, context)),
+                }
+            }
+            // A value such as the stem of a file named `...` is only known now
+            let escapes = |part: &[u8]| part == b"." || part == b"..";
+            if file_name.as_encoded_bytes().split(|&byte| byte == b'/').any(escapes) {
+                return Err("the template produced a `.` or `..` directory");
+            }
+            if !file_name.is_empty() {
+                path.push(&file_name);
+            }
+        }
+
+        if file_name.is_empty() {
+            Err("the template produced an empty file name")
+        } else {
+            Ok(path)
+        }
+    }
+}
+
+fn parse_placeholder(inner: &str) -> Result<Token, TemplateError> {
+    let (name, format) = match inner.split_once(':') {
+        Some((name, format)) => (name, Some(format)),
+        None => (inner, None),
+    };
+    let name = *PLACEHOLDERS
+        .iter()
+        .find(|placeholder| **placeholder == name)
+        .ok_or_else(|| TemplateError::UnknownPlaceholder(name.to_string()))?;
+
+    let width = match format {
+        None => None,
+        Some(format) => {
+            let width = format.strip_prefix('0').and_then(|width| width.parse().ok());
+            match width {
+                Some(width) if NUMERIC_PLACEHOLDERS.contains(&name) => Some(width),
+                _ => return Err(TemplateError::InvalidFormat(name.to_string(), format.to_string())),
+            }
+        }
+    };
+
+    Ok(Token::Placeholder { name, width })
+}
+
+fn value(name: &str, width: Option<usize>, context: &TemplateContext) -> OsString {
+    let datetime = context.datetime;
+    let number = |number: i64| match width {
+        Some(width) => OsString::from(format!("{number:0width$}")),
+        None => OsString::from(number.to_string()),
+    };
+    let path = context.relative_path;
+
+    match name {
+        "year" => number(datetime.year().into()),
+        "month" => number(datetime.month().into()),
+        "day" => number(datetime.day().into()),
+        "hour" => number(datetime.hour().into()),
+        "minute" => number(datetime.minute().into()),
+        "second" => number(datetime.second().into()),
+        "month_name" => datetime.format("%B").to_string().into(),
+        "month_abbr" => datetime.format("%b").to_string().into(),
+        "weekday" => datetime.format("%A").to_string().into(),
+        "weekday_abbr" => datetime.format("%a").to_string().into(),
+        "name" => path.file_name().unwrap_or_default().to_owned(),
+        "stem" => path.file_stem().unwrap_or_default().to_owned(),
+        "ext" => path.extension().unwrap_or_default().to_owned(),
+        "dir" => path.parent().map(Path::as_os_str).unwrap_or(OsStr::new("")).to_owned(),
+        "type" => context.category.into(),
+        _ => unreachable!("placeholders are validated when parsing"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn render(pattern: &str, relative_path: &str) -> Result<PathBuf, &'static str> {
+        let datetime = NaiveDate::from_ymd_opt(2024, 5, 12).unwrap().and_hms_opt(9, 5, 11).unwrap();
+        let context = TemplateContext { datetime, relative_path: Path::new(relative_path), category: "Images" };
+        Template::parse(pattern).expect("Failed to parse template").render(&context)
+    }
+
+    #[test]
+    fn test_render_template() {
+        assert_eq!(
+            render("{year}/{month:02}-{month_name}/{ext}/{stem}_{hour:02}{minute:02}.{ext}", "IMG_1.jpg"),
+            Ok(PathBuf::from("2024/05-May/jpg/IMG_1_0905.jpg"))
+        );
+        assert_eq!(render("{dir}/{{{year}}}/{name}", "trip/day one/a.png"), Ok(PathBuf::from("trip/day one/{2024}/a.png")));
+        assert_eq!(render("{dir}/{name}", "a.png"), Ok(PathBuf::from("a.png")));
+        assert_eq!(render("{type}/{year}/{name}", "a.png"), Ok(PathBuf::from("Images/2024/a.png")));
+        assert!(render("{year}/{ext}", "README").is_err());
+        assert_eq!(render("{stem}/{name}", "src/..."), Err("the template produced a `.` or `..` directory"));
+        assert_eq!(render("{year}/{name}", "src/..."), Ok(PathBuf::from("2024/...")));
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let error = |pattern| Template::parse(pattern).err();
+        assert_eq!(error("{year}/{month"), Some(TemplateError::UnclosedBrace(7)));
+        assert_eq!(error("{year}}/x"), Some(TemplateError::UnexpectedBrace(6)));
+        assert_eq!(error("{yaer}/{name}"), Some(TemplateError::UnknownPlaceholder("yaer".to_string())));
+        assert_eq!(error("{name:02}"), Some(TemplateError::InvalidFormat("name".to_string(), "02".to_string())));
+        assert_eq!(error("/{year}/{name}"), Some(TemplateError::AbsolutePath));
+        assert_eq!(error("{year}/../{name}"), Some(TemplateError::ParentDirectory));
+        assert_eq!(error("{year}/"), Some(TemplateError::MissingFileName));
+    }
+}
@@ -0,0 +1,246 @@
+// Reads the recording date of videos from their container: the `mvhd`/`mdhd` creation time of
+// MP4/QuickTime files, and the DateUTC element of Matroska/WebM files.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use chrono::DateTime;
+
+use crate::bmff;
+use crate::FileDate;
+
+// Seconds from 1904-01-01, the QuickTime epoch, to 1970-01-01.
+const QUICKTIME_EPOCH_OFFSET: i64 = 2_082_844_800;
+// Seconds from 1970-01-01 to 2001-01-01, the Matroska epoch.
+const MATROSKA_EPOCH_OFFSET: i64 = 978_307_200;
+// Top-level atoms older QuickTime files can start with instead of `ftyp`.
+const QUICKTIME_ATOMS: [&[u8; 4]; 5] = [b"moov", b"mdat", b"wide", b"free", b"skip"];
+
+const EBML_HEADER: u64 = 0x1A45_DFA3;
+const SEGMENT: u64 = 0x1853_8067;
+const INFO: u64 = 0x1549_A966;
+const CLUSTER: u64 = 0x1F43_B675;
+const DATE_UTC: u64 = 0x4461;
+
+// Returns `None` for files that are not a supported video container or carry no usable date.
+pub fn creation_date(path: &Path) -> io::Result<Option<FileDate>> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut header = [0; 8];
+    let read = file.read(&mut header)?;
+    let header = &header[..read];
+
+    let result = if header.starts_with(&EBML_HEADER.to_be_bytes()[4..]) {
+        from_matroska(&mut file, len)
+    } else if bmff::is_bmff(header) || QUICKTIME_ATOMS.iter().any(|atom| header.get(4..8) == Some(&atom[..])) {
+        from_quicktime(&mut file, len)
+    } else {
+        Ok(None)
+    };
+
+    match result {
+        // Truncated or malformed containers are treated like missing metadata
+        Err(e) if matches!(e.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData) => Ok(None),
+        result => result,
+    }
+}
+
+fn from_quicktime<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Option<FileDate>> {
+    let Some(moov) = bmff::find(reader, 0, len, b"moov")? else {
+        return Ok(None);
+    };
+    if let Some(mvhd) = bmff::find(reader, moov.start, moov.end, b"mvhd")? {
+        if let Some(date) = read_creation_time(reader, mvhd.start)? {
+            return Ok(Some(date));
+        }
+    }
+
+    // Some encoders leave the movie header empty but fill in the per-track media headers
+    let mut offset = moov.start;
+    while let Some(trak) = bmff::read_header(reader, offset, moov.end)? {
+        offset = trak.end;
+        if &trak.kind != b"trak" {
+            continue;
+        }
+        if let Some(mdhd) = bmff::find_path(reader, trak.start, trak.end, &[b"mdia", b"mdhd"])? {
+            if let Some(date) = read_creation_time(reader, mdhd.start)? {
+                return Ok(Some(date));
+            }
+        }
+    }
+    Ok(None)
+}
+
+// `mvhd` and `mdhd` both start with a version, flags and the creation time in seconds since
+// 1904, as 32 bits in version 0 and 64 bits in version 1. Zero means the time was never set.
+fn read_creation_time<R: Read + Seek>(reader: &mut R, start: u64) -> io::Result<Option<FileDate>> {
+    reader.seek(SeekFrom::Start(start))?;
+    let version = bmff::read_u8(reader)?;
+    reader.seek(SeekFrom::Current(3))?;
+    let seconds = if version == 1 { bmff::read_u64(reader)? } else { bmff::read_u32(reader)?.into() };
+
+    if seconds == 0 {
+        return Ok(None);
+    }
+    let Ok(seconds) = i64::try_from(seconds) else {
+        return Ok(None);
+    };
+    Ok(DateTime::from_timestamp(seconds - QUICKTIME_EPOCH_OFFSET, 0).map(FileDate::Instant))
+}
+
+fn from_matroska<R: Read + Seek>(reader: &mut R, len: u64) -> io::Result<Option<FileDate>> {
+    let mut offset = 0;
+    while offset < len {
+        let (id, size, data_start) = read_element_header(reader, offset)?;
+        if id == SEGMENT {
+            // Live recordings may leave the segment size unknown, i.e. up to the end of the file
+            let end = size.map_or(len, |size| data_start.saturating_add(size).min(len));
+            return find_date_utc(reader, data_start, end);
+        }
+        let Some(size) = size else {
+            return Ok(None);
+        };
+        offset = data_start + size;
+    }
+    Ok(None)
+}
+
+fn find_date_utc<R: Read + Seek>(reader: &mut R, start: u64, end: u64) -> io::Result<Option<FileDate>> {
+    let mut offset = start;
+    while offset < end {
+        let (id, size, data_start) = read_element_header(reader, offset)?;
+        // Metadata comes before the first cluster of media data
+        let (Some(size), false) = (size, id == CLUSTER) else {
+            return Ok(None);
+        };
+
+        if id == INFO {
+            let info_end = data_start + size;
+            let mut child = data_start;
+            while child < info_end {
+                let (id, size, data_start) = read_element_header(reader, child)?;
+                let size = size.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown element size"))?;
+                if id == DATE_UTC && size == 8 {
+                    reader.seek(SeekFrom::Start(data_start))?;
+                    let nanoseconds = bmff::read_u64(reader)? as i64;
+                    if nanoseconds == 0 {
+                        return Ok(None);
+                    }
+                    let seconds = nanoseconds.div_euclid(1_000_000_000) + MATROSKA_EPOCH_OFFSET;
+                    let subsec = nanoseconds.rem_euclid(1_000_000_000) as u32;
+                    return Ok(DateTime::from_timestamp(seconds, subsec).map(FileDate::Instant));
+                }
+                child = data_start + size;
+            }
+            return Ok(None);
+        }
+        offset = data_start + size;
+    }
+    Ok(None)
+}
+
+// Returns the element ID, its data size (`None` when unknown) and where its data starts.
+fn read_element_header<R: Read + Seek>(reader: &mut R, offset: u64) -> io::Result<(u64, Option<u64>, u64)> {
+    reader.seek(SeekFrom::Start(offset))?;
+    let (id, id_len) = read_vint(reader, true)?;
+    let (size, size_len) = read_vint(reader, false)?;
+    let unknown = size == (1 << (7 * size_len)) - 1;
+    Ok((id, (!unknown).then_some(size), offset + (id_len + size_len) as u64))
+}
+
+// EBML variable-length integers: the number of leading zero bits in the first byte gives the
+// length. IDs keep the length marker bit, sizes do not.
+fn read_vint<R: Read>(reader: &mut R, keep_marker: bool) -> io::Result<(u64, usize)> {
+    let first = bmff::read_u8(reader)?;
+    let len = first.leading_zeros() as usize + 1;
+    if len > 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid EBML integer"));
+    }
+
+    let mut value = if keep_marker { first as u64 } else { (first & (0xFF >> len)) as u64 };
+    for _ in 1..len {
+        value = (value << 8) | bmff::read_u8(reader)? as u64;
+    }
+    Ok((value, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use std::fs;
+    use tempdir::TempDir;
+
+    fn bmff_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = (8 + payload.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn header_box(kind: &[u8; 4], version: u8, seconds: u64) -> Vec<u8> {
+        let mut payload = vec![version, 0, 0, 0];
+        if version == 1 {
+            payload.extend_from_slice(&seconds.to_be_bytes());
+        } else {
+            payload.extend_from_slice(&(seconds as u32).to_be_bytes());
+        }
+        payload.extend_from_slice(&[0; 16]);
+        bmff_box(kind, &payload)
+    }
+
+    fn mp4(moov_children: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = bmff_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend_from_slice(&bmff_box(b"mdat", &[0; 32]));
+        data.extend_from_slice(&bmff_box(b"moov", &moov_children.concat()));
+        data
+    }
+
+    fn element(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut element = id.to_vec();
+        element.push(0x80 | data.len() as u8);
+        element.extend_from_slice(data);
+        element
+    }
+
+    fn date_of(name: &str, data: &[u8]) -> Option<FileDate> {
+        let temp_dir = TempDir::new("test_video").expect("Failed to create temp dir");
+        let path = temp_dir.path().join(name);
+        fs::write(&path, data).expect("Failed to write test file");
+        creation_date(&path).expect("Failed to read video")
+    }
+
+    fn instant(year: i32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> Option<FileDate> {
+        Some(FileDate::Instant(Utc.with_ymd_and_hms(year, month, day, hour, minute, second).unwrap()))
+    }
+
+    #[test]
+    fn test_mvhd_creation_time() {
+        let seconds = (1_715_506_211 + QUICKTIME_EPOCH_OFFSET) as u64;
+        assert_eq!(date_of("clip.mp4", &mp4(&[header_box(b"mvhd", 0, seconds)])), instant(2024, 5, 12, 9, 30, 11));
+        assert_eq!(date_of("clip.mov", &mp4(&[header_box(b"mvhd", 1, seconds)])), instant(2024, 5, 12, 9, 30, 11));
+    }
+
+    #[test]
+    fn test_zero_mvhd_falls_back_to_mdhd() {
+        let seconds = QUICKTIME_EPOCH_OFFSET as u64;
+        let trak = bmff_box(b"trak", &bmff_box(b"mdia", &header_box(b"mdhd", 0, seconds)));
+        assert_eq!(date_of("clip.mp4", &mp4(&[header_box(b"mvhd", 0, 0), trak])), instant(1970, 1, 1, 0, 0, 0));
+        assert_eq!(date_of("clip.mp4", &mp4(&[header_box(b"mvhd", 0, 0)])), None);
+    }
+
+    #[test]
+    fn test_matroska_date_utc() {
+        let nanoseconds: i64 = (1_715_506_211 - MATROSKA_EPOCH_OFFSET) * 1_000_000_000;
+        let info = element(&[0x15, 0x49, 0xA9, 0x66], &element(&[0x44, 0x61], &nanoseconds.to_be_bytes()));
+        let mut data = element(&[0x1A, 0x45, 0xDF, 0xA3], &element(&[0x42, 0x82], b"webm"));
+        // Segment of unknown size
+        data.extend_from_slice(&[0x18, 0x53, 0x80, 0x67, 0xFF]);
+        data.extend_from_slice(&info);
+
+        assert_eq!(date_of("clip.mkv", &data), instant(2024, 5, 12, 9, 30, 11));
+        assert_eq!(date_of("notes.txt", b"not a video"), None);
+    }
+}
@@ -0,0 +1,115 @@
+use std::fs::{self, DirEntry};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::vec;
+
+use crate::error::{DorgError, IoContext, IoOperation};
+
+// Directory names that are never descended into, such as dorg's own journal directory.
+pub const IGNORED_DIRS: [&str; 1] = [".dorg"];
+
+pub struct Candidate {
+    pub path: PathBuf,
+    // Path relative to the directory being walked, including the file name.
+    pub relative_path: PathBuf,
+    // 0 for files directly inside the walked directory, 1 for files one level down, etc.
+    pub depth: usize,
+}
+
+// Depth-first walk over the files below a directory. Each subdirectory is entered once,
+// entries are visited in name order, and directories deeper than `max_depth` are not read.
+pub struct Walker {
+    root: PathBuf,
+    max_depth: usize,
+    stack: Vec<(vec::IntoIter<DirEntry>, usize)>,
+}
+
+impl Walker {
+    pub fn new(root: &Path, max_depth: usize) -> Result<Walker, DorgError> {
+        let entries = read_sorted(root)?;
+        Ok(Walker {
+            root: root.to_path_buf(),
+            max_depth,
+            stack: vec![(entries.into_iter(), 0)],
+        })
+    }
+}
+
+impl Iterator for Walker {
+    type Item = Result<Candidate, DorgError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (entries, depth) = self.stack.last_mut()?;
+            let depth = *depth;
+            let Some(entry) = entries.next() else {
+                self.stack.pop();
+                continue;
+            };
+
+            let path = entry.path();
+            let file_type = match entry.file_type().context(IoOperation::Stat, &path) {
+                Ok(file_type) => file_type,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if file_type.is_dir() {
+                let ignored = IGNORED_DIRS.iter().any(|name| entry.file_name() == *name);
+                if depth < self.max_depth && !ignored {
+                    match read_sorted(&path) {
+                        Ok(children) => self.stack.push((children.into_iter(), depth + 1)),
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                continue;
+            }
+
+            let relative_path = path.strip_prefix(&self.root).unwrap_or(&path).to_path_buf();
+            return Some(Ok(Candidate { path, relative_path, depth }));
+        }
+    }
+}
+
+fn read_sorted(dir: &Path) -> Result<Vec<DirEntry>, DorgError> {
+    let entries = fs::read_dir(dir).and_then(|entries| entries.collect::<io::Result<Vec<_>>>());
+    let mut entries = entries.context(IoOperation::ReadDir, dir)?;
+    entries.sort_by_key(|entry| entry.file_name());
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempdir::TempDir;
+
+    fn walk(root: &Path, max_depth: usize) -> Vec<PathBuf> {
+        Walker::new(root, max_depth)
+            .expect("Failed to read root")
+            .map(|candidate| candidate.expect("Failed to walk").relative_path)
+            .collect()
+    }
+
+    #[test]
+    fn test_walker_descends_into_each_subdirectory_once() {
+        let temp_dir = TempDir::new("test_walk").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("a").join("b")).unwrap();
+        fs::create_dir_all(root.join(".dorg")).unwrap();
+        File::create(root.join("top.jpg")).unwrap();
+        File::create(root.join("a").join("middle.jpg")).unwrap();
+        File::create(root.join("a").join("b").join("bottom.jpg")).unwrap();
+        File::create(root.join(".dorg").join("journal.log")).unwrap();
+
+        assert_eq!(
+            walk(root, usize::MAX),
+            vec![
+                PathBuf::from("a/b/bottom.jpg"),
+                PathBuf::from("a/middle.jpg"),
+                PathBuf::from("top.jpg"),
+            ]
+        );
+        assert_eq!(walk(root, 1), vec![PathBuf::from("a/middle.jpg"), PathBuf::from("top.jpg")]);
+        assert_eq!(walk(root, 0), vec![PathBuf::from("top.jpg")]);
+    }
+}
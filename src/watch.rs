@@ -0,0 +1,346 @@
+// Notices files that appear in a directory, with inotify on Linux or by rescanning the
+// directory elsewhere, and reports each one once it has stopped changing.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::DorgError;
+use crate::walk::Walker;
+
+// The size and modification time a file had when it was last looked at.
+type Snapshot = (u64, Option<SystemTime>);
+
+pub struct WatchOptions {
+    // How long a file must go unchanged before it is organized.
+    pub settle: Duration,
+    // Rescan the directory instead of using inotify, for network and FUSE file systems where
+    // inotify does not report changes.
+    pub poll: bool,
+}
+
+impl Default for WatchOptions {
+    fn default() -> WatchOptions {
+        WatchOptions { settle: Duration::from_secs(2), poll: false }
+    }
+}
+
+enum Source {
+    #[cfg(target_os = "linux")]
+    Inotify(inotify::Inotify),
+    Poll(Poll),
+}
+
+pub struct Watcher {
+    source: Source,
+    // Why the directory is rescanned although inotify was not turned off.
+    inotify_error: Option<DorgError>,
+    settle: Duration,
+    // Files that changed recently, with when they last did and how they looked then.
+    pending: HashMap<PathBuf, (Instant, Option<Snapshot>)>,
+}
+
+impl Watcher {
+    pub fn new(root: &Path, max_depth: usize, options: &WatchOptions) -> Result<Watcher, DorgError> {
+        let (source, inotify_error) = if options.poll {
+            (Source::Poll(Poll::new(root, max_depth)?), None)
+        } else {
+            inotify_or_poll(root, max_depth)?
+        };
+        Ok(Watcher { source, inotify_error, settle: options.settle, pending: HashMap::new() })
+    }
+
+    pub fn backend(&self) -> &'static str {
+        match self.source {
+            #[cfg(target_os = "linux")]
+            Source::Inotify(_) => "inotify",
+            Source::Poll(_) => "polling",
+        }
+    }
+
+    pub fn inotify_error(&self) -> Option<&DorgError> {
+        self.inotify_error.as_ref()
+    }
+
+    // Waits a little for changes, and returns the files that have now settled.
+    pub fn step(&mut self) -> Result<Vec<PathBuf>, DorgError> {
+        let tick = (self.settle / 2).clamp(Duration::from_millis(10), Duration::from_secs(1));
+        let changed = match &mut self.source {
+            #[cfg(target_os = "linux")]
+            Source::Inotify(inotify) => inotify.changes(tick)?,
+            Source::Poll(poll) => poll.changes(tick)?,
+        };
+
+        let now = Instant::now();
+        for path in changed {
+            let snapshot = snapshot(&path);
+            self.pending.insert(path, (now, snapshot));
+        }
+
+        let mut settled = Vec::new();
+        self.pending.retain(|path, (since, last)| {
+            let current = snapshot(path);
+            if current.is_none() {
+                return false;
+            }
+            if current != *last {
+                *since = now;
+                *last = current;
+                return true;
+            }
+            if now.duration_since(*since) >= self.settle {
+                settled.push(path.clone());
+                return false;
+            }
+            true
+        });
+        settled.sort();
+        Ok(settled)
+    }
+}
+
+// Falls back to rescanning the directory when inotify cannot be used, returning why.
+#[cfg(target_os = "linux")]
+fn inotify_or_poll(root: &Path, max_depth: usize) -> Result<(Source, Option<DorgError>), DorgError> {
+    match inotify::Inotify::new(root, max_depth) {
+        Ok(inotify) => Ok((Source::Inotify(inotify), None)),
+        Err(e) => Ok((Source::Poll(Poll::new(root, max_depth)?), Some(e))),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn inotify_or_poll(root: &Path, max_depth: usize) -> Result<(Source, Option<DorgError>), DorgError> {
+    Ok((Source::Poll(Poll::new(root, max_depth)?), None))
+}
+
+fn snapshot(path: &Path) -> Option<Snapshot> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if metadata.is_dir() {
+        return None;
+    }
+    Some((metadata.len(), metadata.modified().ok()))
+}
+
+// Compares the files below the directory against the previous scan.
+struct Poll {
+    root: PathBuf,
+    max_depth: usize,
+    seen: HashMap<PathBuf, Option<Snapshot>>,
+}
+
+impl Poll {
+    fn new(root: &Path, max_depth: usize) -> Result<Poll, DorgError> {
+        let mut poll = Poll { root: root.to_path_buf(), max_depth, seen: HashMap::new() };
+        // Files that were already there when watching started are left alone
+        poll.scan()?;
+        Ok(poll)
+    }
+
+    fn changes(&mut self, interval: Duration) -> Result<Vec<PathBuf>, DorgError> {
+        thread::sleep(interval);
+        self.scan()
+    }
+
+    // Returns the files that are new or different since the last scan.
+    fn scan(&mut self) -> Result<Vec<PathBuf>, DorgError> {
+        let mut seen = HashMap::new();
+        let mut changed = Vec::new();
+        for candidate in Walker::new(&self.root, self.max_depth)? {
+            let path = candidate?.path;
+            let snapshot = snapshot(&path);
+            if self.seen.get(&path) != Some(&snapshot) {
+                changed.push(path.clone());
+            }
+            seen.insert(path, snapshot);
+        }
+        self.seen = seen;
+        Ok(changed)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod inotify {
+    use std::collections::HashMap;
+    use std::ffi::{CString, OsStr};
+    use std::fs::File;
+    use std::io::{self, Read};
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    use std::time::Duration;
+
+    use crate::error::{DorgError, IoContext, IoOperation};
+    use crate::walk::{Walker, IGNORED_DIRS};
+
+    const EVENTS: u32 = libc::IN_CREATE | libc::IN_MODIFY | libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+    const EVENT_HEADER_LEN: usize = 16;
+
+    pub struct Inotify {
+        file: File,
+        root: PathBuf,
+        max_depth: usize,
+        // Watched directories by watch descriptor, with how deep below the root they are.
+        directories: HashMap<i32, (PathBuf, usize)>,
+    }
+
+    impl Inotify {
+        pub fn new(root: &Path, max_depth: usize) -> Result<Inotify, DorgError> {
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error()).context(IoOperation::Watch, root);
+            }
+            let file = File::from(unsafe { OwnedFd::from_raw_fd(fd) });
+            let mut inotify = Inotify { file, root: root.to_path_buf(), max_depth, directories: HashMap::new() };
+            inotify.watch_tree(root, 0)?;
+            Ok(inotify)
+        }
+
+        fn watch_tree(&mut self, dir: &Path, depth: usize) -> Result<(), DorgError> {
+            let path = CString::new(dir.as_os_str().as_bytes())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+                .context(IoOperation::Watch, dir)?;
+            let wd = unsafe { libc::inotify_add_watch(self.file.as_raw_fd(), path.as_ptr(), EVENTS) };
+            if wd < 0 {
+                return Err(io::Error::last_os_error()).context(IoOperation::Watch, dir);
+            }
+            self.directories.insert(wd, (dir.to_path_buf(), depth));
+
+            if depth < self.max_depth {
+                for entry in std::fs::read_dir(dir).context(IoOperation::ReadDir, dir)? {
+                    let entry = entry.context(IoOperation::ReadDir, dir)?;
+                    let ignored = IGNORED_DIRS.iter().any(|name| entry.file_name() == *name);
+                    let is_dir = entry.file_type().context(IoOperation::Stat, &entry.path())?.is_dir();
+                    if is_dir && !ignored {
+                        self.watch_tree(&entry.path(), depth + 1)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        // Waits up to `timeout` for events and returns the files they were about.
+        pub fn changes(&mut self, timeout: Duration) -> Result<Vec<PathBuf>, DorgError> {
+            let mut pollfd = libc::pollfd { fd: self.file.as_raw_fd(), events: libc::POLLIN, revents: 0 };
+            let timeout = i32::try_from(timeout.as_millis()).unwrap_or(i32::MAX);
+            if unsafe { libc::poll(&mut pollfd, 1, timeout) } < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    return Ok(Vec::new());
+                }
+                return Err(error).context(IoOperation::Watch, &self.root);
+            }
+
+            let mut changed = Vec::new();
+            let mut buffer = [0u8; 64 * 1024];
+            loop {
+                let read = match self.file.read(&mut buffer) {
+                    Ok(read) => read,
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(changed),
+                    Err(e) => return Err(e).context(IoOperation::Watch, &self.root),
+                };
+
+                let mut offset = 0;
+                while offset + EVENT_HEADER_LEN <= read {
+                    let field = |at: usize| {
+                        let start = offset + at;
+                        u32::from_ne_bytes(buffer[start..start + 4].try_into().unwrap())
+                    };
+                    let (wd, mask, len) = (field(0) as i32, field(4), field(12) as usize);
+                    let name = &buffer[offset + EVENT_HEADER_LEN..offset + EVENT_HEADER_LEN + len];
+                    let name = OsStr::from_bytes(name.split(|&byte| byte == 0).next().unwrap_or_default());
+                    offset += EVENT_HEADER_LEN + len;
+
+                    self.handle(wd, mask, name, &mut changed)?;
+                }
+            }
+        }
+
+        fn handle(&mut self, wd: i32, mask: u32, name: &OsStr, changed: &mut Vec<PathBuf>) -> Result<(), DorgError> {
+            if mask & libc::IN_Q_OVERFLOW != 0 {
+                // Events were lost, so every file is looked at again
+                for candidate in Walker::new(&self.root, self.max_depth)? {
+                    changed.push(candidate?.path);
+                }
+                return Ok(());
+            }
+            if mask & libc::IN_IGNORED != 0 {
+                self.directories.remove(&wd);
+                return Ok(());
+            }
+            let Some((dir, depth)) = self.directories.get(&wd).cloned() else {
+                return Ok(());
+            };
+            let path = dir.join(name);
+
+            if mask & libc::IN_ISDIR == 0 {
+                changed.push(path);
+            } else if mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 && depth < self.max_depth {
+                let ignored = IGNORED_DIRS.iter().any(|ignored| name == *ignored);
+                if !ignored && self.watch_tree(&path, depth + 1).is_ok() {
+                    // A directory that was moved in, or filled before its watch was added
+                    for candidate in Walker::new(&path, self.max_depth - depth - 1)? {
+                        changed.push(candidate?.path);
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    fn settled_files(watcher: &mut Watcher) -> Vec<PathBuf> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            let settled = watcher.step().expect("Failed to watch");
+            if !settled.is_empty() {
+                return settled;
+            }
+        }
+        Vec::new()
+    }
+
+    fn watch_new_files(poll: bool) {
+        let temp_dir = TempDir::new("test_watch").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        fs::write(root.join("existing.png"), "old").unwrap();
+        let options = WatchOptions { settle: Duration::from_millis(100), poll };
+        let mut watcher = Watcher::new(root, 1, &options).expect("Failed to watch");
+
+        fs::create_dir(root.join("day1")).unwrap();
+        fs::write(root.join("day1").join("shot.png"), "new").unwrap();
+        fs::write(root.join(".dorg-ignored"), "").unwrap();
+        fs::remove_file(root.join(".dorg-ignored")).unwrap();
+
+        assert_eq!(settled_files(&mut watcher), vec![root.join("day1").join("shot.png")]);
+    }
+
+    #[test]
+    fn test_polling_reports_new_files_once_settled() {
+        watch_new_files(true);
+    }
+
+    #[test]
+    fn test_polling_starts_from_an_empty_directory() {
+        let temp_dir = TempDir::new("test_watch").expect("Failed to create temp dir");
+        let root = temp_dir.path();
+        let options = WatchOptions { settle: Duration::from_millis(100), poll: true };
+        let mut watcher = Watcher::new(root, 0, &options).expect("Failed to watch");
+        watcher.step().expect("Failed to watch");
+
+        fs::write(root.join("shot.png"), "new").unwrap();
+        assert_eq!(settled_files(&mut watcher), vec![root.join("shot.png")]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_inotify_reports_new_files_once_settled() {
+        watch_new_files(false);
+    }
+}
@@ -0,0 +1,73 @@
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+
+// The time zone dates are bucketed in, so a file from 9pm on the 31st lands in that day's
+// folder rather than in the next month's because of its UTC timestamp.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Zone {
+    Local,
+    Utc,
+    Named(Tz),
+}
+
+impl Zone {
+    pub fn parse(zone: &str) -> Option<Zone> {
+        match zone {
+            "local" => Some(Zone::Local),
+            "utc" | "UTC" => Some(Zone::Utc),
+            name => name.parse().ok().map(Zone::Named),
+        }
+    }
+
+    pub fn to_naive(&self, datetime: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Zone::Local => datetime.with_timezone(&Local).naive_local(),
+            Zone::Utc => datetime.naive_utc(),
+            Zone::Named(tz) => datetime.with_timezone(tz).naive_local(),
+        }
+    }
+
+    // `2024-05-12T09:30:11-06:00`, with the zone's offset at that instant.
+    pub fn to_rfc3339(&self, datetime: DateTime<Utc>) -> String {
+        let format = "%Y-%m-%dT%H:%M:%S%:z";
+        match self {
+            Zone::Local => datetime.with_timezone(&Local).format(format).to_string(),
+            Zone::Utc => datetime.format(format).to_string(),
+            Zone::Named(tz) => datetime.with_timezone(tz).format(format).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Datelike, TimeZone, Timelike};
+
+    fn bucket(zone: &str, utc: (i32, u32, u32, u32, u32)) -> (i32, u32, u32, u32) {
+        let (year, month, day, hour, minute) = utc;
+        let datetime = Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap();
+        let local = Zone::parse(zone).expect("Invalid zone").to_naive(datetime);
+        (local.year(), local.month(), local.day(), local.hour())
+    }
+
+    #[test]
+    fn test_evening_before_midnight_stays_on_the_same_day() {
+        // 9pm on January 15th in UTC-6 is already the 16th in UTC
+        assert_eq!(bucket("utc", (2024, 1, 16, 3, 0)), (2024, 1, 16, 3));
+        assert_eq!(bucket("America/Mexico_City", (2024, 1, 16, 3, 0)), (2024, 1, 15, 21));
+    }
+
+    #[test]
+    fn test_month_and_year_boundaries() {
+        assert_eq!(bucket("America/Chicago", (2024, 3, 1, 2, 30)), (2024, 2, 29, 20));
+        assert_eq!(bucket("America/Chicago", (2024, 6, 1, 4, 59)), (2024, 5, 31, 23));
+        assert_eq!(bucket("Asia/Tokyo", (2024, 4, 30, 15, 30)), (2024, 5, 1, 0));
+        assert_eq!(bucket("UTC", (2025, 1, 1, 0, 0)), (2025, 1, 1, 0));
+        assert_eq!(bucket("America/Los_Angeles", (2025, 1, 1, 0, 0)), (2024, 12, 31, 16));
+    }
+
+    #[test]
+    fn test_invalid_zone() {
+        assert_eq!(Zone::parse("Mars/Olympus_Mons"), None);
+    }
+}